@@ -1,10 +1,31 @@
 extern crate nalgebra;
 extern crate pathfinding;
 
-use crate::{constants, road};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug)]
+use crate::{
+    config::SimConfig,
+    error::RoutieError,
+    random::{self, SimRng},
+    results::{LaneMove, StepEvents, TripRecord},
+    road,
+    util::ordered_skip_map::TieBreak,
+    vehicle::VehicleParams,
+};
+
+/// Issued by `Network::add_actor`, in order
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ActorId(pub u64);
+
+impl From<ActorId> for u64 {
+    fn from(id: ActorId) -> u64 {
+        id.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum Agendum {
+    /// stays off-road for this many seconds
     SleepFor(i32),
     TravelTo {
         segment_id: road::SegmentId,
@@ -13,35 +34,124 @@ pub enum Agendum {
     },
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Somewhere off-road, e.g. where a trip begins or ends
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LocationOffRoad {
+    pub segment_id: road::SegmentId,
+    pub segment_side: road::Direction,
+    pub pos_param: road::PosParam,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum RouteStep {
     ArriveAt(f64),
     LaneChange(road::SegmentLaneRank),
     TurnAt(road::JunctionLaneId),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Actor {
-    max_speed: f64,
+    id: ActorId,
+    /// its own stream, so what it draws doesn't depend on what other actors there are
+    rng: SimRng,
+    vehicle: VehicleParams,
+    /// distance per second, as of the start of the time step
+    speed: f64,
     route: Vec<RouteStep>,
     agenda: Vec<Agendum>,
+    /// the `TravelTo` agendum under way, if any
+    trip: Option<Trip>,
+    /// when the `SleepFor` agendum under way, if any, is over
+    wake_time: Option<f64>,
 }
 
-// TODO: clean this up
-#[derive(Debug)]
-pub struct NullRouteError;
-#[derive(Debug)]
-pub struct NullAgendaError;
-#[derive(Debug)]
-struct NoSuchLocationError;
+/// How a trip has gone so far
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Trip {
+    origin: LocationOffRoad,
+    destination: LocationOffRoad,
+    depart_time: f64,
+    distance: f64,
+    route: Vec<road::QualifiedSegmentLaneRank>,
+}
 
 type AgendaStatus = Option<Agendum>;
 
 impl Actor {
-    pub fn new(agenda: Vec<Agendum>) -> Self {
-        Self { max_speed: constants::ACTOR_MAX_SPEED, agenda, route: Vec::new() }
+    /// `seed` is the simulation's
+    pub fn new(id: ActorId, seed: u64, vehicle: VehicleParams, agenda: Vec<Agendum>) -> Self {
+        Self {
+            id,
+            rng: random::new_rng(seed, random::Stream::Actor(id)),
+            vehicle,
+            speed: 0.0,
+            agenda,
+            route: Vec::new(),
+            trip: None,
+            wake_time: None,
+        }
+    }
+
+    pub fn get_id(&self) -> ActorId {
+        self.id
+    }
+
+    pub fn get_rng(&mut self) -> &mut SimRng {
+        &mut self.rng
+    }
+
+    pub fn get_vehicle(&self) -> &VehicleParams {
+        &self.vehicle
     }
 
+    pub fn get_speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Speed at the end of the time step, and distance covered during it, when heading for
+    /// the cruising speed as quickly as the vehicle allows
+    fn accelerate(&self, config: &SimConfig, speed_limit: f64) -> (f64, f64) {
+        let speed_cruising = self.vehicle.get_cruising_speed(speed_limit);
+        let speed_next = if self.speed > speed_cruising {
+            // e.g. just turned onto a slower road
+            (self.speed - self.vehicle.deceleration * config.time_step).max(speed_cruising)
+        } else {
+            (self.speed + self.vehicle.acceleration * config.time_step).min(speed_cruising)
+        };
+        let distance = 0.5 * (self.speed + speed_next) * config.time_step;
+        (speed_next, distance)
+    }
+
+    fn log_distance(&mut self, distance: f64, events: &mut StepEvents) {
+        events.distance += distance;
+        if let Some(trip) = &mut self.trip {
+            trip.distance += distance;
+        }
+    }
+
+    fn trip_add_lane(&mut self, lane: road::QualifiedSegmentLaneRank) {
+        if let Some(trip) = &mut self.trip {
+            trip.route.push(lane);
+        }
+    }
+
+    fn trip_finish(&mut self, arrive_time: f64) -> Option<TripRecord> {
+        let trip = self.trip.take()?;
+        Some(TripRecord {
+            vehicle_class: self.vehicle.class,
+            origin: trip.origin,
+            destination: trip.destination,
+            depart_time: trip.depart_time,
+            arrive_time,
+            distance: trip.distance,
+            route: trip.route,
+        })
+    }
+
+    /// A stack, so the next step is last
+    pub fn get_route(&self) -> &[RouteStep] {
+        &self.route
+    }
     pub fn route_push(&mut self, item: RouteStep) {
         self.route.push(item)
     }
@@ -50,23 +160,27 @@ impl Actor {
         self.route.last().copied()
     }
 
-    pub fn route_pop(&mut self) -> Result<RouteStep, NullRouteError> {
-        if self.route.len() > 0 {
-            Ok(self.route.remove(self.route.len() - 1))
-        } else {
-            Err(NullRouteError)
-        }
+    pub fn route_pop(&mut self) -> Result<RouteStep, RoutieError> {
+        self.route.pop().ok_or(RoutieError::MalformedAgenda)
     }
 
+    /// last is next
+    pub fn get_agenda(&self) -> &[Agendum] {
+        &self.agenda
+    }
     pub fn agenda_peek(&self) -> AgendaStatus {
         self.agenda.last().copied()
     }
-    pub fn agenda_pop(&mut self) -> Result<Agendum, NullAgendaError> {
-        if self.agenda.len() > 0 {
-            Ok(self.agenda.remove(self.agenda.len() - 1))
-        } else {
-            Err(NullAgendaError)
-        }
+    pub fn agenda_pop(&mut self) -> Result<Agendum, RoutieError> {
+        self.agenda.pop().ok_or(RoutieError::MalformedAgenda)
+    }
+}
+
+/// Actors sharing a position in a lane are ordered by ID
+impl TieBreak for Actor {
+    type Tie = ActorId;
+    fn get_tie(&self) -> ActorId {
+        self.id
     }
 }
 
@@ -89,16 +203,17 @@ pub enum ActorContext<'a> {
     },
 }
 
-fn to_on_road_location(
+/// The lane an actor off-road at the given spot gets onto, and where along it
+pub fn to_on_road_location(
     segment_ctx: &road::SegmentContext,
     segment_side: road::Direction,
     pos_param: road::PosParam,
-) -> Result<(road::Direction, road::SegmentLaneRank, road::PosParam), NoSuchLocationError> {
+) -> Result<(road::Direction, road::SegmentLaneRank, road::PosParam), RoutieError> {
     use road::Direction::{Backward, Forward};
     let segment = segment_ctx.segment;
     let (lane_direction, lane_rank) =
         match (segment_side, segment.backward_lanes.len(), segment.forward_lanes.len()) {
-            (_, 0, 0) => Err(NoSuchLocationError),
+            (_, 0, 0) => Err(RoutieError::NoLanesOnSegment(segment_ctx.id)),
             (Backward, 0, _) => Ok((Forward, segment.forward_lanes.first_idx())),
             (Forward, _, 0) => Ok((Backward, segment.backward_lanes.last_idx())),
             (Forward, _, _) => Ok((Forward, segment.forward_lanes.last_idx())),
@@ -108,8 +223,72 @@ fn to_on_road_location(
     Ok((lane_direction, lane_rank, pos_param))
 }
 
+/// Onto the side of the segment the lane runs along, unless the actor has nothing left to do
+/// and finished actors are despawned
+fn move_off_road(
+    insertions: &mut road::ActorInsertions,
+    lane_ctx: &road::SegmentLaneContext,
+    pos_param: road::PosParam,
+    actor: Actor,
+) {
+    let config = lane_ctx.segment_ctx.network.get_config();
+    if !(actor.agenda.is_empty() && config.despawn_finished_actors) {
+        insertions.push_off_road(lane_ctx.segment_ctx.id, lane_ctx.direction, pos_param, actor)
+    }
+}
+
+/// Seconds from the end of `input` to the end of `output` at cruising speed, in
+/// milliseconds since route search needs costs that are `Ord`
+fn get_route_step_cost(
+    network: &road::Network,
+    vehicle: &VehicleParams,
+    input: road::QualifiedSegmentLaneRank,
+    output @ (segment_id, direction, rank): road::QualifiedSegmentLaneRank,
+) -> Result<u64, RoutieError> {
+    let junction_id = network.get_lane_end_junction(input)?;
+    let junction = network.get_junction(junction_id)?;
+    let junction_ctx = &road::JunctionContext::new(network, junction_id, junction);
+    let junction_lane_id = junction
+        .get_lane_for_segment_lanes(input, output)
+        .ok_or(RoutieError::UnreachableDestination { from: input, to: segment_id })?;
+    let junction_lane = junction
+        .lanes
+        .get(&junction_lane_id)
+        .ok_or(RoutieError::UnknownJunctionLane(junction_lane_id))?;
+    let junction_lane_ctx =
+        road::JunctionLaneContext::new(junction_ctx, junction_lane_id, junction_lane);
+
+    let segment = network.get_segment(segment_id)?;
+    let segment_ctx = &road::SegmentContext::new(network, segment_id, segment);
+    let segment_lane = network.get_segment_lane(output)?;
+    let segment_lane_ctx =
+        road::SegmentLaneContext::new(segment_ctx, direction, rank, segment_lane);
+
+    let time = junction_lane_ctx.get_length()?
+        / vehicle.get_cruising_speed(junction_lane_ctx.get_speed_limit())
+        + segment_lane_ctx.get_length()?
+            / vehicle.get_cruising_speed(segment_lane_ctx.get_speed_limit());
+    // closed roads (zero limit) cost as much as possible without overflowing the sum
+    Ok((1000.0 * time).round().min(u32::MAX as f64) as u64)
+}
+
 impl ActorContext<'_> {
-    pub fn advance(&self, network_pp: &mut road::Network) {
+    pub fn get_actor(&self) -> &Actor {
+        match self {
+            ActorContext::OffRoad { actor, .. }
+            | ActorContext::OnRoadSegment { actor, .. }
+            | ActorContext::OnRoadJunction { actor, .. } => actor,
+        }
+    }
+
+    /// `time` is when the step begins. The actor's next state goes into `insertions`, to be
+    /// merged into the next network. On error, nothing has gone into `insertions`.
+    pub fn advance(
+        &self,
+        insertions: &mut road::ActorInsertions,
+        time: f64,
+        events: &mut StepEvents,
+    ) -> Result<(), RoutieError> {
         // naming conventions:
         // - road componenets and actors may be undecorated (current world) or _pp ("plus-plus") (next world)
         // - road components and scalars may be undecorated (current) or _next
@@ -118,61 +297,133 @@ impl ActorContext<'_> {
                 match actor.agenda_peek() {
                     None => {
                         // stay put
-                        let segment_pp = network_pp.segments.get_mut(&segment_ctx.id).unwrap();
-                        match segment_side {
-                            road::Direction::Forward => &mut segment_pp.forward_actors,
-                            road::Direction::Backward => &mut segment_pp.backward_actors,
-                        }
-                        .insert(*pos_param, (*actor).clone())
+                        insertions.push_off_road(
+                            segment_ctx.id,
+                            *segment_side,
+                            *pos_param,
+                            (*actor).clone(),
+                        )
                     }
                     Some(agendum) => {
                         match agendum {
-                            Agendum::SleepFor(time) => todo!(),
+                            Agendum::SleepFor(duration) => {
+                                let mut actor_pp = (*actor).clone();
+                                let wake_time =
+                                    *actor_pp.wake_time.get_or_insert(time + duration as f64);
+                                if time >= wake_time {
+                                    // the next agendum is taken up on the next step
+                                    actor_pp.agenda_pop()?;
+                                    actor_pp.wake_time = None;
+                                }
+                                insertions.push_off_road(
+                                    segment_ctx.id,
+                                    *segment_side,
+                                    *pos_param,
+                                    actor_pp,
+                                )
+                            }
                             Agendum::TravelTo {
                                 segment_id: segment_id_dest,
                                 segment_side: segment_side_dest,
                                 pos_param: pos_param_dest,
                             } => {
                                 let mut actor_pp = (*actor).clone();
-                                actor_pp.agenda_pop().unwrap();
-
-                                // setup route. TODO: actual pathfinding, build route
-                                // if pos_param_dest < *pos_param {
-                                //     actor_pp.route_push(RouteStep::ArriveAt(pos_param_dest));
-                                //     actor_pp.route_push(RouteStep::TurnAt(0.into()));
-                                // } else {
-                                //     actor_pp.route_push(RouteStep::ArriveAt(pos_param_dest));
-                                // }
+                                actor_pp.agenda_pop()?;
+                                let origin = LocationOffRoad {
+                                    segment_id: segment_ctx.id,
+                                    segment_side: *segment_side,
+                                    pos_param: *pos_param,
+                                };
+                                let destination = LocationOffRoad {
+                                    segment_id: segment_id_dest,
+                                    segment_side: segment_side_dest,
+                                    pos_param: pos_param_dest,
+                                };
 
                                 let (lane_direction_next, lane_rank_next, pos_param_next) =
-                                    to_on_road_location(segment_ctx, *segment_side, *pos_param)
-                                        .unwrap();
+                                    to_on_road_location(segment_ctx, *segment_side, *pos_param)?;
+
+                                let segment_ctx_dest = road::SegmentContext::new(
+                                    segment_ctx.network,
+                                    segment_id_dest,
+                                    segment_ctx.network.get_segment(segment_id_dest)?,
+                                );
+                                let (lane_direction_dest, lane_rank_dest, pos_param_dest) =
+                                    to_on_road_location(
+                                        &segment_ctx_dest,
+                                        segment_side_dest,
+                                        pos_param_dest,
+                                    )?;
 
                                 let start: road::QualifiedSegmentLaneRank =
                                     (segment_ctx.id, lane_direction_next, lane_rank_next);
+                                let goal: road::QualifiedSegmentLaneRank =
+                                    (segment_id_dest, lane_direction_dest, lane_rank_dest);
+                                let get_junction = |segment_lane| {
+                                    let junction_id =
+                                        segment_ctx.network.get_lane_end_junction(segment_lane)?;
+                                    segment_ctx.network.get_junction(junction_id)
+                                };
+                                // nodes carry whether a junction has been passed, so that a
+                                // destination behind the start on the same lane is reached by
+                                // going around rather than by the empty route
+                                let ahead = pos_param_dest >= pos_param_next;
                                 let route_raw = pathfinding::prelude::astar(
-                                    start,
-                                    |segment_lane @ (segment_id, direction, rank): road::QualifiedSegmentLaneRank| -> Vec<(road::QualifiedSegmentLaneRank, u32)>{
-                                        let (begin_junction_id, end_junction_id) = network_pp.get_segment_junctions(segment_id).unwrap();
-                                        let junction = network_pp.junctions.get(
-                                            &match direction {
-                                                road::Direction::Forward => end_junction_id,
-                                                road::Direction::Backward => begin_junction_id,
-                                            }
-                                        ).unwrap();
-                                        const cost: u32 = 1; // TODO
-                                        junction.get_outputs_for_input(segment_lane).into_iter().map(|step| (step, cost)).collect()
-                                    }
+                                    &(start, false),
+                                    |&(segment_lane, _)| {
+                                        get_junction(segment_lane)
+                                            .map(|junction| {
+                                                junction.get_outputs_for_input(segment_lane)
+                                            })
+                                            .unwrap_or_default()
+                                            .into_iter()
+                                            .filter_map(|step| {
+                                                let cost = get_route_step_cost(
+                                                    segment_ctx.network,
+                                                    actor.get_vehicle(),
+                                                    segment_lane,
+                                                    step,
+                                                )
+                                                .ok()?;
+                                                Some(((step, true), cost))
+                                            })
+                                            .collect::<Vec<_>>()
+                                    },
+                                    |_| 0,
+                                    |&(segment_lane, moved)| {
+                                        segment_lane == goal && (moved || ahead)
+                                    },
                                 );
 
-                                let lane_next_pp = network_pp
-                                    .segments
-                                    .get_mut(&segment_ctx.id)
-                                    .unwrap()
-                                    .get_lanes_mut(lane_direction_next)
-                                    .get_mut(&lane_rank_next)
-                                    .unwrap();
-                                lane_next_pp.actors.insert(pos_param_next, actor_pp);
+                                // route is a stack, so push in reverse
+                                let (path, _) =
+                                    route_raw.ok_or(RoutieError::UnreachableDestination {
+                                        from: start,
+                                        to: segment_id_dest,
+                                    })?;
+                                let path: Vec<_> = path
+                                    .into_iter()
+                                    .map(|(segment_lane, _)| segment_lane)
+                                    .collect();
+                                actor_pp.route_push(RouteStep::ArriveAt(pos_param_dest));
+                                for step in path.windows(2).rev() {
+                                    let junction_lane_id = get_junction(step[0])?
+                                        .get_lane_for_segment_lanes(step[0], step[1])
+                                        .ok_or(RoutieError::UnreachableDestination {
+                                            from: step[0],
+                                            to: step[1].0,
+                                        })?;
+                                    actor_pp.route_push(RouteStep::TurnAt(junction_lane_id));
+                                }
+
+                                actor_pp.trip = Some(Trip {
+                                    origin,
+                                    destination,
+                                    depart_time: time,
+                                    distance: 0.0,
+                                    route: vec![start],
+                                });
+                                insertions.push_segment_lane(start, pos_param_next, actor_pp);
                             }
                         }
                     }
@@ -180,46 +431,97 @@ impl ActorContext<'_> {
             }
             ActorContext::OnRoadSegment { pos_param, lane_ctx, actor } => {
                 let mut actor_pp = (*actor).clone();
-                let segment_pp = network_pp.segments.get_mut(&lane_ctx.segment_ctx.id).unwrap();
-                let lane_pp =
-                    segment_pp.get_lanes_mut(lane_ctx.direction).get_mut(&lane_ctx.rank).unwrap();
-                // TODO: account for lane length
-                let pos_param_next_naive = pos_param + actor.max_speed * constants::SIM_TIME_STEP;
+                let segment_lane = (lane_ctx.segment_ctx.id, lane_ctx.direction, lane_ctx.rank);
+                let lane_length = lane_ctx.get_length()?;
+                let config = lane_ctx.segment_ctx.network.get_config();
+                let (speed_next, distance) = actor.accelerate(config, lane_ctx.get_speed_limit());
+                actor_pp.speed = speed_next;
+                let pos_param_next_naive = pos_param + distance / lane_length;
+                let lane_move = |pos_param_next| LaneMove {
+                    lane: segment_lane,
+                    from: *pos_param,
+                    to: pos_param_next,
+                    speed: speed_next,
+                    vehicle_length: actor.vehicle.length,
+                };
                 match actor.route_peek() {
                     None => {
-                        // done, move off road
-                        match lane_ctx.direction {
-                            road::Direction::Forward => &mut segment_pp.forward_actors,
-                            road::Direction::Backward => &mut segment_pp.backward_actors,
-                        }
-                        .insert(*pos_param, actor_pp)
+                        // routes end with `ArriveAt`, which moves off road, so only actors
+                        // placed on a lane without a route get here
+                        actor_pp.speed = 0.0;
+                        events.trips.extend(actor_pp.trip_finish(time));
+                        move_off_road(insertions, lane_ctx, *pos_param, actor_pp);
                     }
                     Some(step) => match step {
                         RouteStep::ArriveAt(pos_param_target) => {
                             if pos_param_next_naive >= pos_param_target {
-                                actor_pp.route_pop().unwrap();
-                                lane_pp.actors.insert(pos_param_target, actor_pp);
+                                // done, move off road
+                                actor_pp.route_pop()?;
+                                actor_pp.speed = 0.0;
+                                actor_pp.log_distance(
+                                    (pos_param_target - pos_param) * lane_length,
+                                    events,
+                                );
+                                events.lane_moves.push(lane_move(pos_param_target));
+                                events.trips.extend(actor_pp.trip_finish(time + config.time_step));
+                                move_off_road(insertions, lane_ctx, pos_param_target, actor_pp);
                             } else {
-                                lane_pp.actors.insert(pos_param_next_naive, actor_pp);
+                                actor_pp.log_distance(distance, events);
+                                events.lane_moves.push(lane_move(pos_param_next_naive));
+                                insertions.push_segment_lane(
+                                    segment_lane,
+                                    pos_param_next_naive,
+                                    actor_pp,
+                                );
                             }
                         }
-                        RouteStep::LaneChange(lane_rank) => todo!(),
+                        RouteStep::LaneChange(lane_rank) => {
+                            // moves across while moving along, at the same fraction of the way
+                            let lane_next = (segment_lane.0, segment_lane.1, lane_rank);
+                            lane_ctx.segment_ctx.network.get_segment_lane(lane_next)?;
+                            actor_pp.route_pop()?;
+                            actor_pp.trip_add_lane(lane_next);
+                            // junctions are entered from the new lane, on the next step
+                            let pos_param_next = pos_param_next_naive.min(1.0);
+                            let distance_next = (pos_param_next - pos_param) * lane_length;
+                            actor_pp.log_distance(distance_next, events);
+                            events.lane_moves.push(lane_move(pos_param_next));
+                            insertions.push_segment_lane(lane_next, pos_param_next, actor_pp);
+                        }
                         RouteStep::TurnAt(lane_id) => {
-                            if pos_param_next_naive > 1.0 {
-                                let (begin_junction_id, end_junction_id) = network_pp
-                                    .get_segment_junctions(lane_ctx.segment_ctx.id)
-                                    .unwrap();
-                                let junction_pp = network_pp
-                                    .junctions
-                                    .get_mut(&match lane_ctx.direction {
-                                        road::Direction::Backward => begin_junction_id,
-                                        road::Direction::Forward => end_junction_id,
-                                    })
-                                    .unwrap();
-                                let lane_pp = junction_pp.lanes.get_mut(&lane_id).unwrap();
-                                lane_pp.actors.insert(pos_param_next_naive - 1.0, actor_pp)
+                            let network = lane_ctx.segment_ctx.network;
+                            let junction_id = network.get_lane_end_junction(segment_lane)?;
+                            let must_yield =
+                                network.get_junction(junction_id)?.must_yield(lane_id)?;
+                            if pos_param_next_naive > 1.0 && must_yield {
+                                // wait at the stop line
+                                actor_pp.speed = 0.0;
+                                actor_pp.log_distance((1.0 - pos_param) * lane_length, events);
+                                events.lane_moves.push(lane_move(1.0));
+                                insertions.push_segment_lane(segment_lane, 1.0, actor_pp);
+                            } else if pos_param_next_naive > 1.0 {
+                                actor_pp.log_distance(distance, events);
+                                events.lane_exits.push(segment_lane);
+                                events.lane_moves.push(lane_move(pos_param_next_naive));
+                                let overshoot = (pos_param_next_naive - 1.0) * lane_length;
+                                let junction_lane_length =
+                                    network.get_junction_lane_length(junction_id, lane_id)?;
+                                // junction lanes can be shorter than one step
+                                let pos_param_next = (overshoot / junction_lane_length).min(1.0);
+                                insertions.push_junction_lane(
+                                    junction_id,
+                                    lane_id,
+                                    pos_param_next,
+                                    actor_pp,
+                                )
                             } else {
-                                lane_pp.actors.insert(pos_param_next_naive, actor_pp);
+                                actor_pp.log_distance(distance, events);
+                                events.lane_moves.push(lane_move(pos_param_next_naive));
+                                insertions.push_segment_lane(
+                                    segment_lane,
+                                    pos_param_next_naive,
+                                    actor_pp,
+                                );
                             }
                         }
                     },
@@ -227,33 +529,38 @@ impl ActorContext<'_> {
             }
             ActorContext::OnRoadJunction { pos_param, lane_ctx, actor } => {
                 let mut actor_pp = (*actor).clone();
-                // TODO: account for lane length
-                let pos_param_next_naive = pos_param + actor.max_speed * constants::SIM_TIME_STEP;
+                let lane_length = lane_ctx.get_length()?;
+                let config = lane_ctx.junction_ctx.network.get_config();
+                let (speed_next, distance) = actor.accelerate(config, lane_ctx.get_speed_limit());
+                actor_pp.speed = speed_next;
+                actor_pp.log_distance(distance, events);
+                let pos_param_next_naive = pos_param + distance / lane_length;
                 if pos_param_next_naive > 1.0 {
-                    actor_pp.route_pop().unwrap();
-                    let (_, (segment_id, direction, segment_lane_rank)) = lane_ctx
-                        .junction_ctx
-                        .junction
-                        .get_segment_lanes_for_junction_lane(lane_ctx.id);
-                    let segment_pp = network_pp.segments.get_mut(&segment_id).unwrap();
-                    let lane_pp = match direction {
-                        road::Direction::Backward => &mut segment_pp.backward_lanes,
-                        road::Direction::Forward => &mut segment_pp.forward_lanes,
-                    }
-                    .get_mut(&segment_lane_rank)
-                    .unwrap();
-                    lane_pp.actors.insert(pos_param_next_naive - 1.0, actor_pp)
+                    actor_pp.route_pop()?;
+                    let (_, segment_lane) = lane_ctx.get_segment_lanes()?;
+                    actor_pp.trip_add_lane(segment_lane);
+                    let overshoot = (pos_param_next_naive - 1.0) * lane_length;
+                    let segment_lane_length =
+                        lane_ctx.junction_ctx.network.get_segment_lane_length(segment_lane)?;
+                    let pos_param_next = (overshoot / segment_lane_length).min(1.0);
+                    events.lane_moves.push(LaneMove {
+                        lane: segment_lane,
+                        from: 0.0,
+                        to: pos_param_next,
+                        speed: speed_next,
+                        vehicle_length: actor.vehicle.length,
+                    });
+                    insertions.push_segment_lane(segment_lane, pos_param_next, actor_pp)
                 } else {
-                    let lane_pp = network_pp
-                        .junctions
-                        .get_mut(&lane_ctx.junction_ctx.id)
-                        .unwrap()
-                        .lanes
-                        .get_mut(&lane_ctx.id)
-                        .unwrap();
-                    lane_pp.actors.insert(pos_param_next_naive, actor_pp);
+                    insertions.push_junction_lane(
+                        lane_ctx.junction_ctx.id,
+                        lane_ctx.id,
+                        pos_param_next_naive,
+                        actor_pp,
+                    );
                 }
             }
         }
+        Ok(())
     }
 }
@@ -0,0 +1,100 @@
+use std::collections::{HashMap, HashSet};
+
+use pathfinding::prelude::{bfs_reach, strongly_connected_components};
+
+use crate::road::{
+    Direction::{Backward, Forward},
+    Network, QualifiedSegmentLaneRank,
+};
+
+/// Segment lanes, linked wherever a junction lane leads from one to the other.
+/// Lanes on segments not linked to junctions at both ends are left out.
+#[derive(Debug)]
+pub struct LaneGraph {
+    lanes: Vec<QualifiedSegmentLaneRank>,
+    successors: HashMap<QualifiedSegmentLaneRank, Vec<QualifiedSegmentLaneRank>>,
+    predecessors: HashMap<QualifiedSegmentLaneRank, Vec<QualifiedSegmentLaneRank>>,
+}
+
+impl LaneGraph {
+    pub fn new(network: &Network) -> Self {
+        let mut lanes = Vec::new();
+        let mut successors = HashMap::new();
+        let mut predecessors: HashMap<_, Vec<_>> = HashMap::new();
+        for (segment_id, segment) in network.segments.enumerate() {
+            for direction in [Forward, Backward] {
+                for (rank, _) in segment.get_lanes(direction).enumerate() {
+                    let lane = (segment_id, direction, rank);
+                    let junction = match network
+                        .get_lane_end_junction(lane)
+                        .and_then(|junction_id| network.get_junction(junction_id))
+                    {
+                        Ok(junction) => junction,
+                        // not linked to a junction, which `Network::validate` reports
+                        Err(_) => continue,
+                    };
+                    let outputs: Vec<QualifiedSegmentLaneRank> =
+                        junction.get_outputs_for_input(lane).into_iter().collect();
+                    for output in &outputs {
+                        predecessors.entry(*output).or_default().push(lane);
+                    }
+                    successors.insert(lane, outputs);
+                    lanes.push(lane);
+                }
+            }
+        }
+        Self { lanes, successors, predecessors }
+    }
+
+    pub fn get_lanes(&self) -> &[QualifiedSegmentLaneRank] {
+        &self.lanes
+    }
+
+    pub fn get_successors(&self, lane: QualifiedSegmentLaneRank) -> &[QualifiedSegmentLaneRank] {
+        self.successors.get(&lane).map_or(&[], |lanes| lanes)
+    }
+
+    pub fn get_predecessors(&self, lane: QualifiedSegmentLaneRank) -> &[QualifiedSegmentLaneRank] {
+        self.predecessors.get(&lane).map_or(&[], |lanes| lanes)
+    }
+
+    /// Groups of lanes from which every other lane in the group can be reached.
+    /// In a well-formed network this is a single group.
+    pub fn strongly_connected_components(&self) -> Vec<Vec<QualifiedSegmentLaneRank>> {
+        strongly_connected_components(&self.lanes, |lane| self.get_successors(*lane).to_vec())
+    }
+
+    /// Lanes an actor on `lane` can get to, including `lane` itself
+    pub fn reachable_from(
+        &self,
+        lane: QualifiedSegmentLaneRank,
+    ) -> HashSet<QualifiedSegmentLaneRank> {
+        bfs_reach(lane, |lane| self.get_successors(*lane).to_vec()).collect()
+    }
+
+    /// Lanes from which an actor can get to `lane`, including `lane` itself
+    pub fn reachable_to(
+        &self,
+        lane: QualifiedSegmentLaneRank,
+    ) -> HashSet<QualifiedSegmentLaneRank> {
+        bfs_reach(lane, |lane| self.get_predecessors(*lane).to_vec()).collect()
+    }
+
+    pub fn is_reachable(
+        &self,
+        from: QualifiedSegmentLaneRank,
+        to: QualifiedSegmentLaneRank,
+    ) -> bool {
+        bfs_reach(from, |lane| self.get_successors(*lane).to_vec()).any(|lane| lane == to)
+    }
+
+    /// Lanes with no way out
+    pub fn get_sink_lanes(&self) -> Vec<QualifiedSegmentLaneRank> {
+        self.lanes.iter().copied().filter(|lane| self.get_successors(*lane).is_empty()).collect()
+    }
+
+    /// Lanes with no way in
+    pub fn get_source_lanes(&self) -> Vec<QualifiedSegmentLaneRank> {
+        self.lanes.iter().copied().filter(|lane| self.get_predecessors(*lane).is_empty()).collect()
+    }
+}
@@ -0,0 +1,323 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::{self, RGB},
+    error::ConfigError,
+    road::RoadClass,
+    vehicle::{self, VehicleClass, VehicleClassSpec},
+};
+
+/// Everything that affects simulation results
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimConfig {
+    /// seconds of simulated time per step
+    pub time_step: f64,
+    /// seconds of simulated time to run for
+    pub duration: f64,
+    pub seed: u64,
+    /// remove actors once they've got off the road with nothing left on their agenda
+    pub despawn_finished_actors: bool,
+    /// seconds of simulated time per detector reading
+    pub detector_interval: f64,
+    /// classes missing here get `VehicleClass::default_spec`
+    pub vehicle_classes: BTreeMap<VehicleClass, VehicleClassSpec>,
+    /// relative share of each class among sampled vehicles
+    pub vehicle_mix: BTreeMap<VehicleClass, f64>,
+    /// distance per second, for segments without a limit of their own.
+    /// Classes missing here are unlimited.
+    pub speed_limits: BTreeMap<RoadClass, f64>,
+    pub lane_width: f64,
+    /// room to spare on a segment beyond its lanes, as a percentage of their width
+    pub segment_wiggle_room_pct: u32,
+    /// footprint radius for junctions with no linked segments
+    pub junction_radius: f64,
+    pub curve_flattening_tolerance: f64,
+    pub roundabout_shape_points: usize,
+}
+
+/// How actors are colored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActorColoring {
+    /// by vehicle class, see `RenderConfig::vehicle_colors`
+    Class,
+    /// from `actor_slow_color` when stopped to `actor_fast_color` at `actor_fast_speed`
+    Speed,
+}
+
+/// Everything that only affects how results look
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderConfig {
+    /// render while simulating; otherwise only a replay, if any, is rendered later
+    pub live: bool,
+    /// width and height, in pixels
+    pub image_size: i32,
+    pub frame_rate: i32,
+    pub output_path: String,
+    /// top left corner of the area shown, in network coordinates
+    pub viewport_origin: (f64, f64),
+    /// width and height of the area shown, in network units
+    pub viewport_size: f64,
+    pub actor_coloring: ActorColoring,
+    /// classes missing here get `actor_color`
+    pub vehicle_colors: BTreeMap<VehicleClass, RGB>,
+    pub actor_color: RGB,
+    pub actor_slow_color: RGB,
+    pub actor_fast_color: RGB,
+    /// distance per second
+    pub actor_fast_speed: f64,
+    pub junction_color: RGB,
+    pub lane_color: RGB,
+    pub lane_width: f64,
+    pub lane_arrow_size: f64,
+    pub segment_color: RGB,
+    pub filled_shape_border_width: f64,
+}
+
+/// What to measure, and where to write it at the end of a run. Metrics are only gathered
+/// if there's somewhere to write them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// seconds of simulated time per row
+    pub interval: f64,
+    /// a row per segment lane per interval
+    pub lanes_csv_path: Option<String>,
+    /// a row per interval
+    pub network_csv_path: Option<String>,
+    /// both of the above
+    pub json_path: Option<String>,
+    /// a row per detector per interval, written regardless of `is_enabled`
+    pub detectors_csv_path: Option<String>,
+}
+
+/// Where to save the simulation as it runs, and what to resume from
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CheckpointConfig {
+    /// overwritten at each save
+    pub save_path: Option<String>,
+    /// seconds of simulated time between saves; if unset, only saved at the end of the run
+    pub interval: Option<f64>,
+    /// carries on from here instead of building the scenario, keeping the saved sim config
+    /// apart from `duration`
+    pub restore_path: Option<String>,
+}
+
+/// Where to record a replay of the run, or a replay to render instead of simulating
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplayConfig {
+    pub record_path: Option<String>,
+    /// renders this with the `[render]` settings, then exits
+    pub play_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub sim: SimConfig,
+    pub render: RenderConfig,
+    pub metrics: MetricsConfig,
+    pub checkpoint: CheckpointConfig,
+    pub replay: ReplayConfig,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            time_step: constants::SIM_TIME_STEP,
+            duration: constants::SIM_TIME_DURATION,
+            seed: 0,
+            despawn_finished_actors: constants::SIM_DESPAWN_FINISHED_ACTORS,
+            detector_interval: constants::SIM_DETECTOR_INTERVAL,
+            vehicle_classes: vehicle::default_vehicle_classes(),
+            vehicle_mix: vehicle::default_vehicle_mix(),
+            speed_limits: [
+                (RoadClass::Motorway, constants::ROAD_SPEED_LIMIT_MOTORWAY),
+                (RoadClass::Arterial, constants::ROAD_SPEED_LIMIT_ARTERIAL),
+                (RoadClass::Residential, constants::ROAD_SPEED_LIMIT_RESIDENTIAL),
+            ]
+            .into_iter()
+            .collect(),
+            lane_width: constants::ROAD_LANE_WIDTH,
+            segment_wiggle_room_pct: constants::ROAD_SEGMENT_WIGGLE_ROOM_PCT,
+            junction_radius: constants::ROAD_JUNCTION_RADIUS,
+            curve_flattening_tolerance: constants::ROAD_CURVE_FLATTENING_TOLERANCE,
+            roundabout_shape_points: constants::ROAD_ROUNDABOUT_SHAPE_POINTS,
+        }
+    }
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            live: true,
+            image_size: constants::RENDER_IMAGE_SIZE,
+            frame_rate: constants::SIM_FRAME_RATE,
+            output_path: constants::RENDER_OUTPUT_PATH.to_string(),
+            viewport_origin: (0.0, 0.0),
+            viewport_size: 1.0,
+            actor_coloring: ActorColoring::Class,
+            vehicle_colors: [
+                (VehicleClass::Car, constants::ACTOR_COLOR),
+                (VehicleClass::Truck, constants::ACTOR_TRUCK_COLOR),
+                (VehicleClass::Bus, constants::ACTOR_BUS_COLOR),
+                (VehicleClass::Bicycle, constants::ACTOR_BICYCLE_COLOR),
+            ]
+            .into_iter()
+            .collect(),
+            actor_color: constants::ACTOR_COLOR,
+            actor_slow_color: constants::ACTOR_SLOW_COLOR,
+            actor_fast_color: constants::ACTOR_FAST_COLOR,
+            actor_fast_speed: constants::ACTOR_FAST_SPEED,
+            junction_color: constants::ROAD_JUNCTION_COLOR,
+            lane_color: constants::ROAD_LANE_COLOR,
+            lane_width: constants::ROAD_LANE_WIDTH_VISUAL,
+            lane_arrow_size: constants::ROAD_LANE_ARROW_SIZE,
+            segment_color: constants::ROAD_SEGMENT_COLOR,
+            filled_shape_border_width: constants::FILLED_SHAPE_BORDER_WIDTH,
+        }
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            interval: constants::METRICS_INTERVAL,
+            lanes_csv_path: None,
+            network_csv_path: None,
+            json_path: None,
+            detectors_csv_path: None,
+        }
+    }
+}
+
+impl MetricsConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.lanes_csv_path.is_some() || self.network_csv_path.is_some() || self.json_path.is_some()
+    }
+}
+
+impl SimConfig {
+    pub fn get_vehicle_spec(&self, class: VehicleClass) -> VehicleClassSpec {
+        self.vehicle_classes.get(&class).cloned().unwrap_or_else(|| class.default_spec())
+    }
+
+    pub fn get_speed_limit(&self, class: RoadClass) -> f64 {
+        self.speed_limits.get(&class).copied().unwrap_or(f64::INFINITY)
+    }
+}
+
+impl RenderConfig {
+    pub fn get_vehicle_color(&self, class: VehicleClass) -> RGB {
+        self.vehicle_colors.get(&class).copied().unwrap_or(self.actor_color)
+    }
+
+    pub fn get_speed_color(&self, speed: f64) -> RGB {
+        let t = (speed / self.actor_fast_speed).clamp(0.0, 1.0);
+        let mix = |slow: f64, fast: f64| slow + t * (fast - slow);
+        let (slow, fast) = (self.actor_slow_color, self.actor_fast_color);
+        (mix(slow.0, fast.0), mix(slow.1, fast.1), mix(slow.2, fast.2))
+    }
+}
+
+impl Config {
+    /// `--config <path>` reads a TOML file with optional `[sim]`, `[render]`, `[metrics]`,
+    /// `[checkpoint]` and `[replay]` tables, then each `--set <table>.<key>=<value>` overrides
+    /// a single value, e.g. `--set sim.time_step=0.5` or `--set sim.speed_limits.motorway=0.2`.
+    /// Anything left out keeps its default.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut table = toml::value::Table::new();
+        let mut settings = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => {
+                    let path = args.next().ok_or(ConfigError::BadArgument(arg))?;
+                    table = read_table(path)?;
+                }
+                "--set" => settings.push(args.next().ok_or(ConfigError::BadArgument(arg))?),
+                _ => return Err(ConfigError::BadArgument(arg)),
+            }
+        }
+        for setting in settings {
+            apply_setting(&mut table, &setting)?;
+        }
+        from_table(table)
+    }
+}
+
+fn read_table(path: impl AsRef<Path>) -> Result<toml::value::Table, ConfigError> {
+    let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+    toml::from_str(&text).map_err(ConfigError::Parse)
+}
+
+fn from_table(table: toml::value::Table) -> Result<Config, ConfigError> {
+    toml::Value::Table(table).try_into().map_err(ConfigError::Parse)
+}
+
+fn apply_setting(table: &mut toml::value::Table, setting: &str) -> Result<(), ConfigError> {
+    let bad_argument = || ConfigError::BadArgument(setting.to_string());
+    let (path, value) = setting.split_once('=').ok_or_else(bad_argument)?;
+    let keys: Vec<&str> = path.split('.').collect();
+    let (key, tables) = match keys.split_last() {
+        Some((key, tables)) if !tables.is_empty() && keys.iter().all(|key| !key.is_empty()) => {
+            (key, tables)
+        }
+        _ => return Err(bad_argument()),
+    };
+    // anything that isn't valid TOML is taken to be a bare string
+    let value = toml::from_str::<toml::value::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut parsed| parsed.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()));
+    // tables along the way are created as needed
+    let mut table = table;
+    for (depth, name) in tables.iter().enumerate() {
+        table = table
+            .entry(*name)
+            .or_insert_with(|| toml::Value::Table(toml::value::Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| ConfigError::NotATable(keys[..=depth].join(".")))?;
+    }
+    table.insert(key.to_string(), value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_settings(settings: &[&str]) -> Result<Config, ConfigError> {
+        let args = settings.iter().flat_map(|setting| ["--set".to_string(), setting.to_string()]);
+        Config::from_args(args)
+    }
+
+    #[test]
+    fn settings_reach_nested_tables() {
+        let config =
+            from_settings(&["sim.time_step=0.5", "sim.speed_limits.motorway=0.2"]).unwrap();
+        assert_eq!(config.sim.time_step, 0.5);
+        assert_eq!(config.sim.get_speed_limit(RoadClass::Motorway), 0.2);
+    }
+
+    #[test]
+    fn settings_below_values_are_rejected() {
+        assert!(matches!(
+            from_settings(&["sim.time_step=0.5", "sim.time_step.fraction=0.5"]),
+            Err(ConfigError::NotATable(path)) if path == "sim.time_step"
+        ));
+        // left to its default, so only found out when the tables are read
+        assert!(matches!(
+            from_settings(&["sim.time_step.fraction=0.5"]),
+            Err(ConfigError::Parse(_))
+        ));
+        assert!(matches!(from_settings(&["time_step=0.5"]), Err(ConfigError::BadArgument(_))));
+        assert!(matches!(from_settings(&["sim..seed=1"]), Err(ConfigError::BadArgument(_))));
+    }
+}
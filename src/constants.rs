@@ -1,13 +1,25 @@
-type RGB = (f64, f64, f64);
+// defaults for `config::SimConfig` and `config::RenderConfig`
+
+pub type RGB = (f64, f64, f64);
 
 pub const SIM_TIME_STEP: f64 = 2.0;
 pub const SIM_TIME_DURATION: f64 = 200.0;
 pub const SIM_FRAME_RATE: i32 = 5;
+pub const SIM_DESPAWN_FINISHED_ACTORS: bool = true;
+pub const SIM_DETECTOR_INTERVAL: f64 = 30.0;
 
 pub const ACTOR_COLOR: RGB = (0.1, 0.7, 0.1);
+pub const ACTOR_TRUCK_COLOR: RGB = (0.8, 0.5, 0.1);
+pub const ACTOR_BUS_COLOR: RGB = (0.9, 0.8, 0.1);
+pub const ACTOR_BICYCLE_COLOR: RGB = (0.1, 0.5, 0.9);
+pub const ACTOR_SLOW_COLOR: RGB = (0.9, 0.1, 0.1);
+pub const ACTOR_FAST_COLOR: RGB = (0.1, 0.7, 0.1);
+pub const ACTOR_FAST_SPEED: f64 = 0.02;
 pub const ACTOR_RADIUS_VISUAL: f64 = 0.01;
 pub const ACTOR_MAX_SPEED: f64 = 0.02;
 
+pub const ROAD_CURVE_FLATTENING_TOLERANCE: f64 = 0.0001;
+
 pub const ROAD_JUNCTION_COLOR: RGB = (0.7, 0.7, 0.7);
 pub const ROAD_JUNCTION_RADIUS: f64 = 0.05;
 
@@ -16,7 +28,18 @@ pub const ROAD_LANE_COLOR: RGB = (0.7, 0.3, 0.7);
 pub const ROAD_LANE_WIDTH: f64 = 0.025;
 pub const ROAD_LANE_WIDTH_VISUAL: f64 = 0.005;
 
+pub const ROAD_ROUNDABOUT_SHAPE_POINTS: usize = 8;
+
+pub const ROAD_SPEED_LIMIT_MOTORWAY: f64 = 0.03;
+pub const ROAD_SPEED_LIMIT_ARTERIAL: f64 = 0.02;
+pub const ROAD_SPEED_LIMIT_RESIDENTIAL: f64 = 0.012;
+
 pub const ROAD_SEGMENT_COLOR: RGB = (1.0, 1.0, 1.0);
 pub const ROAD_SEGMENT_WIGGLE_ROOM_PCT: u32 = 20;
 
 pub const FILLED_SHAPE_BORDER_WIDTH: f64 = 0.001;
+
+pub const METRICS_INTERVAL: f64 = 60.0;
+
+pub const RENDER_IMAGE_SIZE: i32 = 600;
+pub const RENDER_OUTPUT_PATH: &str = "./out.mp4";
@@ -0,0 +1,295 @@
+use std::collections::{HashMap, HashSet};
+
+use rand::{seq::SliceRandom, Rng};
+use rand_distr::{Distribution, Poisson};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actor::{self, Agendum},
+    analysis::LaneGraph,
+    error::RoutieError,
+    road::{Direction, Network, PosParam, QualifiedSegmentLaneRank, SegmentContext, SegmentId},
+    vehicle::VehicleParams,
+};
+
+/// Where trips begin or end
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Place {
+    Segment(SegmentId),
+    /// added with `Demand::add_zone`
+    Zone(String),
+}
+
+/// How departures are spread over time. Either way they are Poisson arrivals, so only
+/// the average rate is set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DepartureProfile {
+    /// trips per second
+    Constant(f64),
+    /// (seconds since the start, trips per second) points in time order, e.g. a rush hour
+    /// peak. The rate is interpolated linearly between points and held beyond the ends.
+    TimeOfDay(Vec<(f64, f64)>),
+}
+
+/// One cell of the origin-destination matrix
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trips {
+    pub origin: Place,
+    pub destination: Place,
+    pub profile: DepartureProfile,
+}
+
+/// Spawns actors over the course of a simulation, each with a single `TravelTo` agendum.
+/// Trip ends are picked at random within their place, among those that can be reached.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Demand {
+    zones: HashMap<String, Vec<SegmentId>>,
+    trips: Vec<Trips>,
+    /// with the topology version it was built for, so it's rebuilt after the network is edited
+    #[serde(skip)]
+    lane_graph: Option<(u64, LaneGraph)>,
+    /// lanes reachable from each start lane seen so far, cleared with the lane graph
+    #[serde(skip)]
+    reachable: HashMap<QualifiedSegmentLaneRank, HashSet<QualifiedSegmentLaneRank>>,
+}
+
+/// Somewhere off-road, as in `Agendum::TravelTo`
+type Spot = (SegmentId, Direction, PosParam);
+
+impl DepartureProfile {
+    /// Average trips per second at `time`
+    pub fn get_rate(&self, time: f64) -> f64 {
+        match self {
+            DepartureProfile::Constant(rate) => *rate,
+            DepartureProfile::TimeOfDay(points) => {
+                match points.iter().position(|(point_time, _)| *point_time > time) {
+                    None => points.last().map_or(0.0, |(_, rate)| *rate),
+                    Some(0) => points[0].1,
+                    Some(idx) => {
+                        let (time_a, rate_a) = points[idx - 1];
+                        let (time_b, rate_b) = points[idx];
+                        rate_a + (rate_b - rate_a) * (time - time_a) / (time_b - time_a)
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Demand {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces any zone of the same name
+    pub fn add_zone(&mut self, name: &str, segments: Vec<SegmentId>) {
+        self.zones.insert(name.to_string(), segments);
+    }
+
+    pub fn add_trips(&mut self, origin: Place, destination: Place, profile: DepartureProfile) {
+        self.trips.push(Trips { origin, destination, profile });
+    }
+
+    pub fn get_trips(&self) -> &[Trips] {
+        &self.trips
+    }
+
+    fn get_segments(&self, place: &Place) -> Result<Vec<SegmentId>, RoutieError> {
+        match place {
+            Place::Segment(segment_id) => Ok(vec![*segment_id]),
+            Place::Zone(name) => match self.zones.get(name) {
+                None => Err(RoutieError::UnknownZone(name.clone())),
+                Some(segments) if segments.is_empty() => Err(RoutieError::EmptyZone(name.clone())),
+                Some(segments) => Ok(segments.clone()),
+            },
+        }
+    }
+
+    /// As `get_segments`, without those with no lanes, which trips can't start or end on
+    fn get_usable_segments(
+        &self,
+        network: &Network,
+        place: &Place,
+    ) -> Result<Vec<SegmentId>, RoutieError> {
+        let mut segments = self.get_segments(place)?;
+        for segment_id in &segments {
+            network.get_segment(*segment_id)?;
+        }
+        segments.retain(|segment_id| has_lanes(network, *segment_id));
+        Ok(segments)
+    }
+
+    /// Builds the lane graph if there isn't one for the network as it is now
+    fn update_lane_graph(&mut self, network: &Network) -> Result<(), RoutieError> {
+        let version = network.get_topology_version();
+        if self.lane_graph.as_ref().is_some_and(|(built_for, _)| *built_for == version) {
+            return Ok(());
+        }
+        self.lane_graph = Some((version, LaneGraph::new(network)));
+        self.reachable.clear();
+        // once per edit rather than on every step
+        for trips in &self.trips {
+            for place in [&trips.origin, &trips.destination] {
+                for segment_id in self.get_segments(place)? {
+                    if network.get_segment(segment_id).is_ok() && !has_lanes(network, segment_id) {
+                        log::warn!(
+                            "No trips start or end on {:?} in {:?}, since it has no lanes",
+                            segment_id,
+                            place
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the actors departing between `time` and the end of the step, off-road at
+    /// their origins. Returns how many there were.
+    pub fn spawn(
+        &mut self,
+        network: &mut Network,
+        time: f64,
+        rng: &mut impl Rng,
+    ) -> Result<usize, RoutieError> {
+        self.update_lane_graph(network)?;
+        let (_, lane_graph) = self.lane_graph.as_ref().unwrap();
+        let config = network.get_config();
+        let mut departures = Vec::new();
+        for trips in &self.trips {
+            let origins = self.get_usable_segments(network, &trips.origin)?;
+            let destinations = self.get_usable_segments(network, &trips.destination)?;
+            if origins.is_empty() || destinations.is_empty() {
+                continue;
+            }
+            // rate halfway through the step
+            let mean = trips.profile.get_rate(time + 0.5 * config.time_step) * config.time_step;
+            // not positive, or not a number
+            let count = Poisson::new(mean).map_or(0.0, |poisson| poisson.sample(rng)) as usize;
+            for _ in 0..count {
+                let origin = pick_spot(network, &origins, rng)?;
+                let start_lane = get_start_lane(network, origin)?;
+                // one search per start lane, rather than one per candidate
+                let reachable = self
+                    .reachable
+                    .entry(start_lane)
+                    .or_insert_with(|| lane_graph.reachable_from(start_lane));
+                let mut candidates = Vec::new();
+                for destination_id in destinations.iter().filter(|id| **id != origin.0) {
+                    for side in [Direction::Forward, Direction::Backward] {
+                        let destination = (*destination_id, side, rng.gen_range(0.0..1.0));
+                        let end_lane = get_start_lane(network, destination)?;
+                        if reachable.contains(&end_lane) {
+                            candidates.push(destination);
+                        }
+                    }
+                }
+                match candidates.choose(rng) {
+                    None => log::warn!(
+                        "Skipped a trip from {:?}: none of its destinations can be reached",
+                        origin.0
+                    ),
+                    Some((segment_id, segment_side, pos_param)) => {
+                        let agendum = Agendum::TravelTo {
+                            segment_id: *segment_id,
+                            segment_side: *segment_side,
+                            pos_param: *pos_param,
+                        };
+                        let vehicle = VehicleParams::sample_any(config, rng);
+                        departures.push((origin, vehicle, agendum));
+                    }
+                }
+            }
+        }
+
+        let count = departures.len();
+        for ((segment_id, segment_side, pos_param), vehicle, agendum) in departures {
+            network.add_actor(segment_id, pos_param, segment_side, vehicle, vec![agendum])?;
+        }
+        Ok(count)
+    }
+}
+
+fn has_lanes(network: &Network, segment_id: SegmentId) -> bool {
+    network
+        .get_segment(segment_id)
+        .is_ok_and(|segment| segment.forward_lanes.len() + segment.backward_lanes.len() > 0)
+}
+
+/// `segments` mustn't be empty
+fn pick_spot(
+    network: &Network,
+    segments: &[SegmentId],
+    rng: &mut impl Rng,
+) -> Result<Spot, RoutieError> {
+    let segment_id = segments[rng.gen_range(0..segments.len())];
+    network.get_segment(segment_id)?;
+    let side = *[Direction::Forward, Direction::Backward].choose(rng).unwrap();
+    Ok((segment_id, side, rng.gen_range(0.0..1.0)))
+}
+
+/// The lane an actor gets onto from `spot`, or would arrive on if headed there
+fn get_start_lane(
+    network: &Network,
+    (segment_id, side, pos_param): Spot,
+) -> Result<QualifiedSegmentLaneRank, RoutieError> {
+    let segment_ctx = &SegmentContext::new(network, segment_id, network.get_segment(segment_id)?);
+    let (direction, rank, _) = actor::to_on_road_location(segment_ctx, side, pos_param)?;
+    Ok((segment_id, direction, rank))
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use super::*;
+    use crate::{
+        random::{self, Stream},
+        road::UTurnPolicy,
+    };
+
+    /// Counts off-road actors per segment, as spawned
+    fn count_actors(network: &Network, segment_id: SegmentId) -> usize {
+        let segment = network.get_segment(segment_id).unwrap();
+        segment.forward_actors.len() + segment.backward_actors.len()
+    }
+
+    #[test]
+    fn lane_less_segments_are_skipped_until_they_get_lanes() {
+        let mut network = Network::new();
+        let j1 = network.add_junction(Point2::new(0.1, 0.1));
+        let j2 = network.add_junction(Point2::new(0.5, 0.1));
+        let j3 = network.add_junction(Point2::new(0.9, 0.1));
+        let (with_lanes, segment) = network.add_segment(j1, j2).unwrap();
+        segment.add_lane(Direction::Forward);
+        segment.add_lane(Direction::Backward);
+        let (destination, segment) = network.add_segment(j2, j3).unwrap();
+        segment.add_lane(Direction::Forward);
+        segment.add_lane(Direction::Backward);
+        let (lane_less, _) = network.add_segment(j1, j3).unwrap();
+        network.connect_junctions(UTurnPolicy::DeadEnds);
+
+        let mut demand = Demand::new();
+        demand.add_zone("origins", vec![with_lanes, lane_less]);
+        demand.add_trips(
+            Place::Zone("origins".to_string()),
+            Place::Segment(destination),
+            DepartureProfile::Constant(100.0),
+        );
+        let mut rng = random::new_rng(0, Stream::Demand);
+        for step in 0..10 {
+            demand.spawn(&mut network, step as f64, &mut rng).unwrap();
+        }
+        assert!(count_actors(&network, with_lanes) > 0);
+        assert_eq!(count_actors(&network, lane_less), 0);
+
+        let segment = network.get_segment_mut(lane_less).unwrap();
+        segment.add_lane(Direction::Forward);
+        segment.add_lane(Direction::Backward);
+        network.connect_junctions(UTurnPolicy::DeadEnds);
+        for step in 10..20 {
+            demand.spawn(&mut network, step as f64, &mut rng).unwrap();
+        }
+        assert!(count_actors(&network, lane_less) > 0);
+    }
+}
@@ -0,0 +1,204 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{OutputError, RoutieError},
+    results::{self, Intervals, StepEvents},
+    road::{
+        Direction::{Backward, Forward},
+        Network, PosParam, QualifiedSegmentLaneRank,
+    },
+};
+
+define_index_type!(DetectorId);
+
+/// A virtual induction loop across a segment lane
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Detector {
+    pub lane: QualifiedSegmentLaneRank,
+    pub pos_param: PosParam,
+}
+
+/// What a detector saw over one interval
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Reading {
+    count: u64,
+    speed_sum: f64,
+    /// seconds during which an actor was over the detector
+    occupied_time: f64,
+}
+
+/// Detectors, and their readings aggregated over fixed intervals of simulated time
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Detectors {
+    /// never removed, so IDs are indices
+    detectors: Vec<Detector>,
+    /// readings by detector, in the order they were added
+    intervals: Intervals<Vec<Reading>>,
+    /// during the latest step, by detector
+    occupied: Vec<bool>,
+}
+
+/// One detector over one interval
+#[derive(Debug, Clone, Serialize)]
+pub struct DetectorRow {
+    pub detector: usize,
+    pub segment: usize,
+    pub direction: &'static str,
+    pub rank: usize,
+    pub pos_param: PosParam,
+    pub interval_start: f64,
+    pub interval_end: f64,
+    /// actors that passed
+    pub count: u64,
+    /// actors per hour
+    pub flow: f64,
+    /// percentage of the time an actor was over the detector
+    pub occupancy: f64,
+    /// distance per second, averaged over the actors that passed; none if none did
+    pub mean_speed: Option<f64>,
+}
+
+impl Detectors {
+    /// Readings are summed over `interval` seconds at a time
+    pub fn new(interval: f64) -> Self {
+        Self { detectors: Vec::new(), intervals: Intervals::new(interval), occupied: Vec::new() }
+    }
+
+    /// Readings start with the next step. `pos_param` must be within the lane, from 0 to 1.
+    pub fn add(
+        &mut self,
+        network: &Network,
+        lane: QualifiedSegmentLaneRank,
+        pos_param: PosParam,
+    ) -> Result<DetectorId, RoutieError> {
+        network.get_segment_lane(lane)?;
+        // also rules out NaN
+        if !(0.0..=1.0).contains(&pos_param) {
+            return Err(RoutieError::PosParamOutOfRange(pos_param));
+        }
+        self.detectors.push(Detector { lane, pos_param });
+        self.occupied.push(false);
+        Ok(DetectorId::from(self.detectors.len() - 1))
+    }
+
+    pub fn get(&self, id: DetectorId) -> Result<&Detector, RoutieError> {
+        self.detectors.get(usize::from(id)).ok_or(RoutieError::UnknownDetector(id))
+    }
+
+    /// Whether an actor was over the detector at any point during the latest step, e.g. for
+    /// signals that extend their green phase
+    pub fn is_occupied(&self, id: DetectorId) -> Result<bool, RoutieError> {
+        self.get(id)?;
+        Ok(self.occupied[usize::from(id)])
+    }
+
+    /// Reads every detector from the lane moves of the step starting at `time`
+    pub fn record_step(&mut self, network: &Network, time: f64, events: &StepEvents) {
+        let time_step = network.get_config().time_step;
+        let mut seen = Vec::new();
+        for (idx, detector) in self.detectors.iter().enumerate() {
+            let lane_length = match network.get_segment_lane_length(detector.lane) {
+                Ok(lane_length) => lane_length,
+                // removed along with its segment
+                Err(_) => continue,
+            };
+            let mut count = 0;
+            let mut speed_sum = 0.0;
+            let mut fraction_over: f64 = 0.0;
+            for lane_move in events.lane_moves.iter().filter(|m| m.lane == detector.lane) {
+                if lane_move.passes(detector.pos_param) {
+                    count += 1;
+                    speed_sum += lane_move.speed;
+                }
+                // a single loop can't tell vehicles apart, so overlapping ones count once
+                fraction_over =
+                    fraction_over.max(lane_move.get_fraction_over(detector.pos_param, lane_length));
+            }
+            seen.push((idx, count, speed_sum, fraction_over));
+        }
+
+        let readings = &mut self.intervals.record_step(time, time_step).totals;
+        // detectors may have been added since the interval began
+        readings.resize(self.detectors.len(), Reading::default());
+        for (idx, count, speed_sum, fraction_over) in &seen {
+            let reading = &mut readings[*idx];
+            reading.count += count;
+            reading.speed_sum += speed_sum;
+            reading.occupied_time += fraction_over * time_step;
+        }
+        for (idx, _, _, fraction_over) in seen {
+            self.occupied[idx] = fraction_over > 0.0;
+        }
+    }
+
+    pub fn get_rows(&self) -> Vec<DetectorRow> {
+        let mut rows = Vec::new();
+        for interval in self.intervals.iter() {
+            for (idx, detector) in self.detectors.iter().enumerate() {
+                let reading = match interval.totals.get(idx) {
+                    Some(reading) => reading,
+                    // added later
+                    None => continue,
+                };
+                let (segment_id, direction, rank) = detector.lane;
+                rows.push(DetectorRow {
+                    detector: idx,
+                    segment: usize::from(segment_id),
+                    direction: match direction {
+                        Forward => "forward",
+                        Backward => "backward",
+                    },
+                    rank: usize::from(rank),
+                    pos_param: detector.pos_param,
+                    interval_start: interval.start,
+                    interval_end: interval.get_end(),
+                    count: reading.count,
+                    flow: reading.count as f64 / interval.duration * 3600.0,
+                    occupancy: 100.0 * reading.occupied_time / interval.duration,
+                    mean_speed: match reading.count {
+                        0 => None,
+                        count => Some(reading.speed_sum / count as f64),
+                    },
+                });
+            }
+        }
+        rows
+    }
+
+    /// A row per detector per interval
+    pub fn write_csv(&self, path: impl AsRef<Path>) -> Result<(), OutputError> {
+        results::write_csv(path, self.get_rows())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use super::*;
+
+    #[test]
+    fn detector_must_be_within_lane() {
+        let mut network = Network::new();
+        let west = network.add_junction(Point2::new(0.2, 0.5));
+        let east = network.add_junction(Point2::new(0.8, 0.5));
+        let (segment_id, segment) = network.add_segment(west, east).unwrap();
+        segment.add_lane(Forward);
+        let lane = (segment_id, Forward, segment.get_lanes(Forward).first_idx());
+        let mut detectors = Detectors::new(60.0);
+
+        for pos_param in [0.0, 0.5, 1.0] {
+            assert!(detectors.add(&network, lane, pos_param).is_ok());
+        }
+        for pos_param in [-0.1, 1.5] {
+            assert_eq!(
+                detectors.add(&network, lane, pos_param),
+                Err(RoutieError::PosParamOutOfRange(pos_param))
+            );
+        }
+        assert!(detectors.add(&network, lane, f64::NAN).is_err());
+        assert_eq!(detectors.detectors.len(), 3);
+    }
+}
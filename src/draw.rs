@@ -1,15 +1,18 @@
 use std::f64::consts::FRAC_PI_2;
-use std::f64::consts::{FRAC_PI_4, PI};
+use std::f64::consts::PI;
+use std::io::Write;
+use std::process::{Child, ChildStdin, Command, Stdio};
 
 use cairo::{Context, ImageSurface};
 use lyon_geom::CubicBezierSegment;
 use nalgebra::{Point2, Rotation2, Vector2};
 
-use crate::constants;
-use crate::spatial::{LineLike, PointLike};
-use crate::{actor, road};
+use crate::config::{ActorColoring, RenderConfig};
+use crate::error::MovieError;
+use crate::replay::{ActorState, Frame};
+use crate::road;
+use crate::spatial::{LineLike, Polyline};
 
-pub const IMAGE_SIZE: i32 = 600;
 const I_HAT: Vector2<f64> = Vector2::new(1.0, 0.0);
 
 fn draw_regular_polygon(cairo_ctx: &cairo::Context, pos: Point2<f64>, n: i8, r: f64, theta_0: f64) {
@@ -23,126 +26,202 @@ fn draw_regular_polygon(cairo_ctx: &cairo::Context, pos: Point2<f64>, n: i8, r:
     cairo_ctx.line_to(start.x, start.y);
 }
 
-fn draw_road_junction_lane(cairo_ctx: &cairo::Context, lane_ctx: &road::JunctionLaneContext) {
-    let (red, green, blue) = constants::ROAD_LANE_COLOR;
+fn draw_polyline(cairo_ctx: &cairo::Context, polyline: &Polyline) {
+    let (first, rest) = polyline.get_points().split_first().unwrap();
+    cairo_ctx.move_to(first.x, first.y);
+    for point in rest {
+        cairo_ctx.line_to(point.x, point.y);
+    }
+}
+
+fn draw_road_junction_lane(
+    cairo_ctx: &cairo::Context,
+    config: &RenderConfig,
+    lane_ctx: &road::JunctionLaneContext,
+) {
+    let (red, green, blue) = config.lane_color;
     cairo_ctx.set_source_rgb(red, green, blue);
-    cairo_ctx.set_line_width(constants::ROAD_LANE_WIDTH_VISUAL);
-    let CubicBezierSegment { from, ctrl1, ctrl2, to } = lane_ctx.get_curve().to_cubic();
+    cairo_ctx.set_line_width(config.lane_width);
+    let CubicBezierSegment { from, ctrl1, ctrl2, to } = match lane_ctx.get_curve() {
+        Ok(curve) => curve,
+        // nothing to draw, which `Network::validate` reports
+        Err(_) => return,
+    };
     cairo_ctx.move_to(from.x, from.y);
     cairo_ctx.curve_to(ctrl1.x, ctrl1.y, ctrl2.x, ctrl2.y, to.x, to.y);
     cairo_ctx.stroke().unwrap();
-
-    for (pos_param, actor) in lane_ctx.lane.actors.enumerate() {
-        let actor_ctx = &actor::ActorContext::OnRoadJunction { pos_param: *pos_param, lane_ctx, actor };
-        draw_actor(cairo_ctx, actor_ctx);
-    }
 }
 
-fn draw_road_junction(cairo_ctx: &cairo::Context, junction_ctx: &road::JunctionContext) {
-    let (red, green, blue) = constants::ROAD_JUNCTION_COLOR;
+fn draw_road_junction(
+    cairo_ctx: &cairo::Context,
+    config: &RenderConfig,
+    junction_ctx: &road::JunctionContext,
+) {
+    let (red, green, blue) = config.junction_color;
     cairo_ctx.set_source_rgb(red, green, blue);
-    cairo_ctx.set_line_width(constants::FILLED_SHAPE_BORDER_WIDTH);
-    draw_regular_polygon(
-        cairo_ctx,
-        junction_ctx.junction.pos,
-        4,
-        constants::ROAD_JUNCTION_RADIUS * f64::sqrt(2.0),
-        FRAC_PI_4,
-    );
+    cairo_ctx.set_line_width(config.filled_shape_border_width);
+    let footprint = junction_ctx.get_footprint();
+    cairo_ctx.move_to(footprint[0].x, footprint[0].y);
+    for corner in &footprint[1..] {
+        cairo_ctx.line_to(corner.x, corner.y);
+    }
+    cairo_ctx.close_path();
     cairo_ctx.stroke().unwrap();
 
     for (id, lane) in junction_ctx.junction.enumerate_lanes() {
-        draw_road_junction_lane(cairo_ctx, &road::JunctionLaneContext::new(junction_ctx, id, lane))
+        draw_road_junction_lane(
+            cairo_ctx,
+            config,
+            &road::JunctionLaneContext::new(junction_ctx, id, lane),
+        )
     }
 }
 
-fn draw_actor(cairo_ctx: &cairo::Context, actor_ctx: &actor::ActorContext) {
-    let (red, green, blue) = constants::ACTOR_COLOR;
+fn draw_actor(cairo_ctx: &cairo::Context, config: &RenderConfig, actor: &ActorState) {
+    let (red, green, blue) = match config.actor_coloring {
+        ActorColoring::Class => config.get_vehicle_color(actor.class),
+        ActorColoring::Speed => config.get_speed_color(actor.speed),
+    };
     cairo_ctx.set_source_rgb(red, green, blue);
-    cairo_ctx.set_line_width(constants::FILLED_SHAPE_BORDER_WIDTH);
+    cairo_ctx.set_line_width(config.filled_shape_border_width);
 
-    let actor_pos = actor_ctx.get_pos();
-    cairo_ctx.arc(actor_pos.x, actor_pos.y, constants::ACTOR_RADIUS_VISUAL, 0.0, 2.0 * PI);
+    cairo_ctx.arc(actor.pos.x, actor.pos.y, actor.radius_visual, 0.0, 2.0 * PI);
     cairo_ctx.fill().unwrap();
 }
 
-fn draw_road_segment_lane(cairo_ctx: &cairo::Context, lane_ctx: &road::SegmentLaneContext) {
-    let (red, green, blue) = constants::ROAD_LANE_COLOR;
+fn draw_road_segment_lane(
+    cairo_ctx: &cairo::Context,
+    config: &RenderConfig,
+    lane_ctx: &road::SegmentLaneContext,
+) {
+    let (red, green, blue) = config.lane_color;
     cairo_ctx.set_source_rgb(red, green, blue);
 
-    cairo_ctx.set_line_width(constants::ROAD_LANE_WIDTH_VISUAL);
-    let (begin_pos, end_pos) = lane_ctx.get_pos();
-    cairo_ctx.move_to(begin_pos.x, begin_pos.y);
-    cairo_ctx.line_to(end_pos.x, end_pos.y);
+    cairo_ctx.set_line_width(config.lane_width);
+    let polyline = match lane_ctx.get_polyline() {
+        Ok(polyline) => polyline,
+        Err(_) => return,
+    };
+    draw_polyline(cairo_ctx, &polyline);
     cairo_ctx.stroke().unwrap();
 
-    cairo_ctx.set_line_width(constants::FILLED_SHAPE_BORDER_WIDTH);
-    let arrow_vec = lane_ctx.get_v(); // can't figure out how to destructure this
+    cairo_ctx.set_line_width(config.filled_shape_border_width);
+    let arrow_vec = polyline.get_tangent(0.5); // can't figure out how to destructure this
     let arrow_theta = FRAC_PI_2 - arrow_vec.x.atan2(arrow_vec.y);
-    let arrow_size = constants::ROAD_LANE_ARROW_SIZE;
-    draw_regular_polygon(cairo_ctx, lane_ctx.get_midpoint(), 3, arrow_size, arrow_theta);
+    let arrow_size = config.lane_arrow_size;
+    draw_regular_polygon(cairo_ctx, polyline.sample(0.5), 3, arrow_size, arrow_theta);
     cairo_ctx.fill().unwrap();
-
-    for (pos_param, actor) in lane_ctx.lane.actors.enumerate() {
-        let actor_ctx =
-            actor::ActorContext::OnRoadSegment { pos_param: *pos_param, lane_ctx, actor };
-        draw_actor(cairo_ctx, &actor_ctx);
-    }
 }
 
-fn draw_road_segment(cairo_ctx: &cairo::Context, segment_ctx: &road::SegmentContext) {
-    let (red, green, blue) = constants::ROAD_SEGMENT_COLOR;
+fn draw_road_segment(
+    cairo_ctx: &cairo::Context,
+    config: &RenderConfig,
+    segment_ctx: &road::SegmentContext,
+) {
+    let (red, green, blue) = config.segment_color;
     cairo_ctx.set_source_rgb(red, green, blue);
     cairo_ctx.set_line_width(segment_ctx.get_width());
 
-    let (begin_pos, end_pos) = segment_ctx.get_pos();
-    cairo_ctx.move_to(begin_pos.x, begin_pos.y);
-    cairo_ctx.line_to(end_pos.x, end_pos.y);
-    cairo_ctx.stroke().unwrap();
-
-    for (pos_param, actor) in segment_ctx.segment.forward_actors.enumerate() {
-        let actor_ctx = actor::ActorContext::OffRoad {
-            pos_param: *pos_param,
-            segment_ctx,
-            segment_side: road::Direction::Forward,
-            actor,
-        };
-        draw_actor(cairo_ctx, &actor_ctx);
-    }
-    for (pos_param, actor) in segment_ctx.segment.backward_actors.enumerate() {
-        let actor_ctx = actor::ActorContext::OffRoad {
-            pos_param: *pos_param,
-            segment_ctx,
-            segment_side: road::Direction::Backward,
-            actor,
-        };
-        draw_actor(cairo_ctx, &actor_ctx);
+    match segment_ctx.get_polyline() {
+        Ok(polyline) => draw_polyline(cairo_ctx, &polyline),
+        Err(_) => return,
     }
+    cairo_ctx.stroke().unwrap();
 
     for (rank, lane) in segment_ctx.segment.forward_lanes.enumerate() {
         draw_road_segment_lane(
             cairo_ctx,
+            config,
             &road::SegmentLaneContext::new(segment_ctx, road::Direction::Forward, rank, lane),
         );
     }
     for (rank, lane) in segment_ctx.segment.backward_lanes.enumerate() {
         draw_road_segment_lane(
             cairo_ctx,
+            config,
             &road::SegmentLaneContext::new(segment_ctx, road::Direction::Backward, rank, lane),
         );
     }
 }
 
-pub fn draw(surface: &ImageSurface, road_network: &road::Network) {
+/// `road_network`'s own actors are left out, in favor of `frame`'s
+pub fn draw(
+    surface: &ImageSurface,
+    config: &RenderConfig,
+    road_network: &road::Network,
+    frame: &Frame,
+) {
     let cairo_ctx = &Context::new(surface).expect("Failed to create Cairo context");
-    cairo_ctx.scale(IMAGE_SIZE as f64, IMAGE_SIZE as f64);
+    let (origin_x, origin_y) = config.viewport_origin;
+    let scale = config.image_size as f64 / config.viewport_size;
+    cairo_ctx.scale(scale, scale);
+    cairo_ctx.translate(-origin_x, -origin_y);
     cairo_ctx.set_line_width(0.01);
     cairo_ctx.set_source_rgb(0.0, 0.0, 0.0);
 
     for (id, segment) in road_network.segments.enumerate() {
-        draw_road_segment(cairo_ctx, &road::SegmentContext::new(road_network, id, segment));
+        draw_road_segment(cairo_ctx, config, &road::SegmentContext::new(road_network, id, segment));
     }
     for (id, junction) in road_network.junctions.enumerate() {
-        draw_road_junction(cairo_ctx, &road::JunctionContext::new(road_network, id, junction));
+        draw_road_junction(
+            cairo_ctx,
+            config,
+            &road::JunctionContext::new(road_network, id, junction),
+        );
+    }
+    // on top of everything else
+    for actor in &frame.actors {
+        draw_actor(cairo_ctx, config, actor);
+    }
+}
+
+/// Frames piped to ffmpeg, which writes the video once this is dropped
+pub struct Movie {
+    config: RenderConfig,
+    ffmpeg: Child,
+    /// `None` once closed, which tells ffmpeg there are no more frames
+    ffmpeg_stdin: Option<ChildStdin>,
+}
+
+impl Movie {
+    // based on https://gist.github.com/tetsu-koba/14083c6705b69017bbc7fb97602f610a
+    pub fn start(config: &RenderConfig) -> Result<Self, MovieError> {
+        let _ = std::fs::remove_file(&config.output_path);
+        let size = format!("{}x{}", config.image_size, config.image_size);
+        let framerate = config.frame_rate.to_string();
+        // run directly rather than through a shell, so the path is never interpreted
+        let mut ffmpeg = Command::new("ffmpeg")
+            .args(["-r", &framerate, "-f", "rawvideo", "-pix_fmt", "bgra", "-s", &size])
+            .args(["-i", "pipe:", "-pix_fmt", "yuv420p", "-r", &framerate, "-y"])
+            .arg(&config.output_path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(MovieError::Io)?;
+        let ffmpeg_stdin = ffmpeg.stdin.take();
+        Ok(Self { config: config.clone(), ffmpeg, ffmpeg_stdin })
+    }
+
+    pub fn add_frame(
+        &mut self,
+        road_network: &road::Network,
+        frame: &Frame,
+    ) -> Result<(), MovieError> {
+        let image_size = self.config.image_size;
+        // TODO: redraw actors only
+        let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, image_size, image_size)
+            .map_err(MovieError::Cairo)?;
+        draw(&surface, &self.config, road_network, frame);
+        let data = surface.take_data().map_err(MovieError::Borrow)?;
+        match self.ffmpeg_stdin.as_mut() {
+            Some(stdin) => stdin.write_all(&data).map_err(MovieError::Io),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Movie {
+    fn drop(&mut self) {
+        drop(self.ffmpeg_stdin.take());
+        self.ffmpeg.wait().expect("child process wasn't running");
     }
 }
@@ -1,18 +1,138 @@
+use std::{fmt, io};
+
 use cairo;
 
+use crate::{
+    actor::ActorId,
+    detectors::DetectorId,
+    road::{JunctionId, JunctionLaneId, QualifiedSegmentLaneRank, SegmentId},
+};
+
 pub type CairoError = cairo::Error;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum RoutieError {
     // AlreadyLinkedSegment,
     // UnlinkedSegment,
-    InvalidId, // TODO: be more specific
+    /// from a store lookup; `Network` methods report the more specific variants below
+    InvalidId,
+    /// refers to something that has since been removed
+    StaleId,
+    UnknownJunction(JunctionId),
+    UnknownSegment(SegmentId),
+    UnknownSegmentLane(QualifiedSegmentLaneRank),
+    UnknownJunctionLane(JunctionLaneId),
+    UnknownDetector(DetectorId),
+    /// no sequence of lanes leads from the lane to the segment
+    UnreachableDestination {
+        from: QualifiedSegmentLaneRank,
+        to: SegmentId,
+    },
+    NoLanesOnSegment(SegmentId),
+    /// positions along a lane run from 0 at its start to 1 at its end
+    PosParamOutOfRange(f64),
+    /// an actor's agenda, or the route planned from it, doesn't match what it's doing
+    MalformedAgenda,
+    /// e.g. a roundabout needs at least two
+    TooFewLinkedSegments(JunctionId),
+    /// not added to the `Demand`
+    UnknownZone(String),
+    EmptyZone(String),
+    /// can't be removed, since this actor is on it, routed through it or headed for it
+    InUse(ActorId),
+}
+
+impl RoutieError {
+    /// Replaces `InvalidId` with something more specific, leaving other errors be
+    pub fn or_unknown(self, unknown: RoutieError) -> RoutieError {
+        match self {
+            RoutieError::InvalidId => unknown,
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for RoutieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use RoutieError::*;
+        match self {
+            InvalidId => write!(f, "no such ID"),
+            StaleId => write!(f, "ID refers to something that has been removed"),
+            UnknownJunction(id) => write!(f, "{:?} doesn't exist", id),
+            UnknownSegment(id) => write!(f, "{:?} doesn't exist", id),
+            UnknownSegmentLane(lane) => write!(f, "{:?} doesn't exist", lane),
+            UnknownJunctionLane(id) => write!(f, "{:?} doesn't exist", id),
+            UnknownDetector(id) => write!(f, "{:?} doesn't exist", id),
+            UnreachableDestination { from, to } => {
+                write!(f, "no route leads from {:?} to {:?}", from, to)
+            }
+            NoLanesOnSegment(id) => write!(f, "{:?} has no lanes", id),
+            PosParamOutOfRange(pos_param) => {
+                write!(f, "{} is outside the lane, which runs from 0 to 1", pos_param)
+            }
+            MalformedAgenda => write!(f, "an actor's agenda doesn't match what it's doing"),
+            TooFewLinkedSegments(id) => write!(f, "{:?} has too few linked segments", id),
+            UnknownZone(name) => write!(f, "zone {:?} doesn't exist", name),
+            EmptyZone(name) => write!(f, "zone {:?} has no segments", name),
+            InUse(id) => write!(f, "{:?} is on it, routed through it or headed for it", id),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    /// not a recognized command line argument, or missing its value
+    BadArgument(String),
+    /// a `--set` path runs through this, which is a value rather than a table
+    NotATable(String),
+}
+
+/// From writing results out
+#[derive(Debug)]
+pub enum OutputError {
+    Io(io::Error),
+    Csv(csv::Error),
+    Json(serde_json::Error),
+}
+
+/// From saving or restoring a simulation
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    Encoding(bincode::Error),
+    /// written by a version of routie whose checkpoints this one can't read
+    UnsupportedVersion(u32),
+}
+
+/// From recording or reading back a replay
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Encoding(bincode::Error),
+    /// written by a version of routie whose replays this one can't read
+    UnsupportedVersion(u32),
+}
+
+/// From rendering a movie
+#[derive(Debug)]
+pub enum MovieError {
+    /// starting ffmpeg, or piping frames to it after it has exited
+    Io(io::Error),
+    Cairo(CairoError),
+    Borrow(cairo::BorrowError),
 }
 
 #[derive(Debug)]
 pub enum GenericError {
     Routie(RoutieError),
     Cairo(CairoError),
+    Config(ConfigError),
+    Output(OutputError),
+    Checkpoint(CheckpointError),
+    Replay(ReplayError),
+    Movie(MovieError),
 }
 
 impl From<RoutieError> for GenericError {
@@ -26,3 +146,33 @@ impl From<CairoError> for GenericError {
         GenericError::Cairo(e)
     }
 }
+
+impl From<ConfigError> for GenericError {
+    fn from(e: ConfigError) -> GenericError {
+        GenericError::Config(e)
+    }
+}
+
+impl From<OutputError> for GenericError {
+    fn from(e: OutputError) -> GenericError {
+        GenericError::Output(e)
+    }
+}
+
+impl From<CheckpointError> for GenericError {
+    fn from(e: CheckpointError) -> GenericError {
+        GenericError::Checkpoint(e)
+    }
+}
+
+impl From<ReplayError> for GenericError {
+    fn from(e: ReplayError) -> GenericError {
+        GenericError::Replay(e)
+    }
+}
+
+impl From<MovieError> for GenericError {
+    fn from(e: MovieError) -> GenericError {
+        GenericError::Movie(e)
+    }
+}
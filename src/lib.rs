@@ -0,0 +1,19 @@
+#[macro_use]
+pub mod util;
+pub mod actor;
+pub mod analysis;
+pub mod config;
+pub mod constants;
+pub mod demand;
+pub mod detectors;
+pub mod draw;
+pub mod error;
+pub mod metrics;
+pub mod random;
+pub mod replay;
+pub mod results;
+pub mod road;
+pub mod simulate;
+pub mod spatial;
+pub mod validate;
+pub mod vehicle;
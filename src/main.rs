@@ -1,34 +1,119 @@
 extern crate cairo;
 extern crate nalgebra;
 
-#[macro_use]
-mod util;
-mod actor;
-mod constants;
-mod draw;
-mod error;
-mod road;
-mod simulate;
-mod spatial;
-
 extern crate log;
 
-use std::io::Write;
-
-use actor::Agendum;
-use draw::IMAGE_SIZE;
 use nalgebra::Point2;
+use routie::{
+    actor::Agendum, config, demand, draw, error, random, replay, results, road, simulate, vehicle,
+};
 
-fn main() {
+fn main() -> Result<(), error::GenericError> {
     env_logger::init();
-    let mut network = road::Network::new();
+    let config = config::Config::from_args(std::env::args().skip(1))?;
+    if let Some(path) = &config.replay.play_path {
+        return play_replay(path, &config.render);
+    }
+    let checkpoint_config = config.checkpoint.clone();
+    let mut sim = match &checkpoint_config.restore_path {
+        Some(path) => simulate::Simulation::restore(path)?,
+        None => build_simulation(&config)?,
+    };
+
+    sim.add_collector(|trip: &results::TripRecord| {
+        log::info!(
+            "{:?} trip from {:?} to {:?} took {:.1}s over {} lanes",
+            trip.vehicle_class,
+            trip.origin.segment_id,
+            trip.destination.segment_id,
+            trip.get_duration(),
+            trip.route.len(),
+        );
+    });
+    if config.render.live {
+        let mut movie = Some(draw::Movie::start(&config.render)?);
+        sim.add_observer(move |sim: &simulate::Simulation| {
+            let network = sim.get_network();
+            if let Some(m) = &mut movie {
+                let frame = replay::Frame::capture(network, sim.get_step_count(), sim.get_time());
+                if let Err(e) = m.add_frame(network, &frame) {
+                    // the simulation itself is unaffected
+                    log::error!("Stopped rendering at step {}: {:?}", sim.get_step_count(), e);
+                    movie = None;
+                }
+            }
+            simulate::Control::Continue
+        });
+    }
+    if let Some(path) = &config.replay.record_path {
+        let mut recorder = replay::ReplayWriter::create(path)?;
+        sim.add_observer(move |sim: &simulate::Simulation| {
+            if let Err(e) = recorder.record(sim) {
+                log::error!("Failed to record step {}: {:?}", sim.get_step_count(), e);
+            }
+            simulate::Control::Continue
+        });
+    }
+
+    // counted from the start, so a restored run ends where the saved one would have
+    let duration = config.sim.duration;
+    let mut until = sim.get_time();
+    while until < duration {
+        until = match checkpoint_config.interval {
+            Some(interval) => (until + interval).min(duration),
+            None => duration,
+        };
+        if let Err(e) = sim.run_until(until) {
+            log::error!("Simulation stopped early: {}", e);
+            break;
+        }
+        if let Some(path) = &checkpoint_config.save_path {
+            sim.save_checkpoint(path)?;
+        }
+    }
+
+    let metrics_config = config.metrics;
+    if let Some(metrics) = sim.get_metrics() {
+        if let Some(path) = &metrics_config.lanes_csv_path {
+            metrics.write_lanes_csv(path)?;
+        }
+        if let Some(path) = &metrics_config.network_csv_path {
+            metrics.write_network_csv(path)?;
+        }
+        if let Some(path) = &metrics_config.json_path {
+            metrics.write_json(path)?;
+        }
+    }
+    if let Some(path) = &metrics_config.detectors_csv_path {
+        sim.get_detectors().write_csv(path)?;
+    }
+    Ok(())
+}
+
+/// Renders a recorded run with the current render settings
+fn play_replay(
+    path: &str,
+    render_config: &config::RenderConfig,
+) -> Result<(), error::GenericError> {
+    let mut replay = replay::ReplayReader::open(path)?;
+    let mut movie = draw::Movie::start(render_config)?;
+    while let Some(frame) = replay.next_frame()? {
+        movie.add_frame(replay.get_network(), &frame)?;
+    }
+    Ok(())
+}
+
+/// The scenario, ready to run
+fn build_simulation(config: &config::Config) -> Result<simulate::Simulation, error::GenericError> {
+    let mut network = road::Network::with_config(config.sim.clone());
+    let mut rng = random::new_rng(config.sim.seed, random::Stream::Scenario);
 
     let j1 = network.add_junction(Point2::new(0.25, 0.25));
     let j2 = network.add_junction(Point2::new(0.25, 0.75));
     let j3 = network.add_junction(Point2::new(0.75, 0.25));
     let j4 = network.add_junction(Point2::new(0.75, 0.75));
 
-    let (s1_id, s1) = network.add_segment(j1, j2);
+    let (s1_id, s1) = network.add_segment(j1, j2)?;
     s1.add_lane(road::Direction::Backward);
     s1.add_lane(road::Direction::Forward);
     // s1.add_actor(
@@ -50,7 +135,7 @@ fn main() {
     //     })],
     // );
 
-    let (s2_id, s2) = network.add_segment(j3, j4);
+    let (_, s2) = network.add_segment(j3, j4)?;
     s2.add_lane(road::Direction::Backward);
     // s2.add_actor(
     //     0.1,
@@ -62,47 +147,38 @@ fn main() {
     //     })],
     // );
 
-    let (s3_id, s3) = network.add_segment(j1, j3);
+    let (s3_id, s3) = network.add_segment(j1, j3)?;
     s3.add_lane(road::Direction::Backward);
-    s3.add_actor(
+    network.add_actor(
+        s3_id,
         0.6,
         road::Direction::Backward,
+        vehicle::VehicleParams::sample_any(&config.sim, &mut rng),
         vec![Agendum::TravelTo {
             segment_id: s1_id,
             segment_side: road::Direction::Forward,
             pos_param: 0.5,
         }],
-    );
-
-    let _s4 = network.add_segment(j2, j4);
+    )?;
 
-    network.connect_junctions();
+    let _s4 = network.add_segment(j2, j4)?;
 
-    // build movie
-    // based on https://gist.github.com/tetsu-koba/14083c6705b69017bbc7fb97602f610a
-    let _ = std::fs::remove_file("./out.mp4");
-    let mut ffmpeg = std::process::Command::new("/bin/sh")
-        .args(&["-c", &format!(
-            "ffmpeg -r {framerate} -f rawvideo -pix_fmt bgra -s {width}x{height} -i pipe: -pix_fmt yuv420p -r {framerate} -y out.mp4",
-            width = draw::IMAGE_SIZE,
-            height = draw::IMAGE_SIZE,
-            framerate = constants::SIM_FRAME_RATE
-        )])
-        .stdin(std::process::Stdio::piped())
-        .spawn()
-        .expect("failed to execute child");
-    let mut frames: Vec<cairo::ImageSurfaceDataOwned> = Vec::new();
-    for _i in 0..((constants::SIM_TIME_DURATION / constants::SIM_TIME_STEP) as i32) {
-        // TODO: redraw actors only
-        let surface =
-            cairo::ImageSurface::create(cairo::Format::ARgb32, IMAGE_SIZE, IMAGE_SIZE).unwrap();
-        draw::draw(&surface, &network);
-        frames.push(surface.take_data().unwrap());
-        network = simulate::advance(network);
+    network.connect_junctions(road::UTurnPolicy::DeadEnds);
+    for issue in network.validate().issues {
+        log::warn!("{}", issue);
     }
-    let ffmpeg_stdin = ffmpeg.stdin.as_mut().unwrap();
-    for frame in frames {
-        ffmpeg_stdin.write_all(&frame).unwrap();
+
+    let mut sim = simulate::Simulation::new(network, config.sim.clone());
+    let mut demand = demand::Demand::new();
+    demand.add_trips(
+        demand::Place::Segment(s3_id),
+        demand::Place::Segment(s1_id),
+        demand::DepartureProfile::TimeOfDay(vec![(0.0, 0.0), (100.0, 0.05), (200.0, 0.0)]),
+    );
+    sim.set_demand(demand);
+    if config.metrics.is_enabled() {
+        sim.enable_metrics(config.metrics.interval);
     }
-    ffmpeg.wait().expect("child process wasn't running");
+    sim.add_detector((s3_id, road::Direction::Backward, road::SegmentLaneRank::from(0)), 0.5)?;
+    Ok(sim)
 }
@@ -0,0 +1,185 @@
+use std::{collections::BTreeMap, fs::File, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::OutputError,
+    results::{self, Intervals, StepEvents},
+    road::{
+        Direction::{Backward, Forward},
+        Network, QualifiedSegmentLaneRank, SegmentContext, SegmentLaneContext,
+    },
+};
+
+/// Running sums for one segment lane over one interval
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct LaneTotals {
+    exits: u64,
+    /// actors per unit length
+    density_sum: f64,
+    speed_sum: f64,
+    speed_samples: u64,
+    /// actors stopped at the lane end, waiting to enter the junction
+    queue_sum: u64,
+    queue_max: u64,
+}
+
+/// Running sums for the whole network over one interval
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct IntervalTotals {
+    steps: u64,
+    lanes: BTreeMap<QualifiedSegmentLaneRank, LaneTotals>,
+    vehicle_distance: f64,
+    vehicle_seconds: f64,
+    completed_trips: u64,
+}
+
+/// KPIs for comparing network designs, aggregated over fixed intervals of simulated time
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Metrics {
+    intervals: Intervals<IntervalTotals>,
+}
+
+/// One segment lane over one interval
+#[derive(Debug, Clone, Serialize)]
+pub struct LaneRow {
+    pub interval_start: f64,
+    pub interval_end: f64,
+    pub segment: usize,
+    pub direction: &'static str,
+    pub rank: usize,
+    /// actors per hour leaving through the lane end
+    pub flow: f64,
+    /// actors per unit length, on average
+    pub density: f64,
+    /// distance per second; none if no actor used the lane
+    pub mean_speed: Option<f64>,
+    pub mean_queue: f64,
+    pub max_queue: u64,
+}
+
+/// The whole network over one interval
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkRow {
+    pub interval_start: f64,
+    pub interval_end: f64,
+    /// in network units, so vehicle-kilometers if a unit is a kilometer
+    pub vehicle_distance: f64,
+    pub vehicle_hours: f64,
+    pub completed_trips: u64,
+}
+
+#[derive(Serialize)]
+struct Report {
+    lanes: Vec<LaneRow>,
+    network: Vec<NetworkRow>,
+}
+
+impl Metrics {
+    /// `interval` is in seconds of simulated time
+    pub fn new(interval: f64) -> Self {
+        Self { intervals: Intervals::new(interval) }
+    }
+
+    /// `network` is as of `time`, the start of the step, and `events` are what happened during it
+    pub fn record_step(&mut self, network: &Network, time: f64, events: &StepEvents) {
+        let time_step = network.get_config().time_step;
+        let interval = &mut self.intervals.record_step(time, time_step).totals;
+        interval.steps += 1;
+        interval.vehicle_distance += events.distance;
+        interval.completed_trips += events.trips.len() as u64;
+        for lane in &events.lane_exits {
+            interval.lanes.entry(*lane).or_default().exits += 1;
+        }
+
+        for (segment_id, segment) in network.segments.enumerate() {
+            let segment_ctx = &SegmentContext::new(network, segment_id, segment);
+            for direction in [Forward, Backward] {
+                for (rank, lane) in segment.get_lanes(direction).enumerate() {
+                    let lane_ctx = SegmentLaneContext::new(segment_ctx, direction, rank, lane);
+                    let totals = interval.lanes.entry((segment_id, direction, rank)).or_default();
+                    let mut count = 0;
+                    let mut queue = 0;
+                    for (pos_param, actor) in lane.actors.enumerate() {
+                        count += 1;
+                        totals.speed_sum += actor.get_speed();
+                        // not those stopped elsewhere, e.g. having just got on from off-road
+                        if *pos_param >= 1.0 && actor.get_speed() <= 0.0 {
+                            queue += 1;
+                        }
+                    }
+                    totals.speed_samples += count;
+                    // none if its segment isn't linked to junctions
+                    if let Ok(lane_length) = lane_ctx.get_length() {
+                        totals.density_sum += count as f64 / lane_length;
+                    }
+                    totals.queue_sum += queue;
+                    totals.queue_max = totals.queue_max.max(queue);
+                    interval.vehicle_seconds += count as f64 * time_step;
+                }
+            }
+        }
+        for (_, junction) in network.junctions.enumerate() {
+            for (_, lane) in junction.lanes.enumerate() {
+                interval.vehicle_seconds += lane.actors.enumerate().count() as f64 * time_step;
+            }
+        }
+    }
+
+    pub fn get_lane_rows(&self) -> Vec<LaneRow> {
+        let mut rows = Vec::new();
+        for interval in self.intervals.iter() {
+            let duration = interval.duration;
+            let steps = interval.totals.steps;
+            for ((segment_id, direction, rank), totals) in &interval.totals.lanes {
+                rows.push(LaneRow {
+                    interval_start: interval.start,
+                    interval_end: interval.get_end(),
+                    segment: usize::from(*segment_id),
+                    direction: match direction {
+                        Forward => "forward",
+                        Backward => "backward",
+                    },
+                    rank: usize::from(*rank),
+                    flow: totals.exits as f64 / duration * 3600.0,
+                    density: totals.density_sum / steps as f64,
+                    mean_speed: match totals.speed_samples {
+                        0 => None,
+                        samples => Some(totals.speed_sum / samples as f64),
+                    },
+                    mean_queue: totals.queue_sum as f64 / steps as f64,
+                    max_queue: totals.queue_max,
+                });
+            }
+        }
+        rows
+    }
+
+    pub fn get_network_rows(&self) -> Vec<NetworkRow> {
+        self.intervals
+            .iter()
+            .map(|interval| NetworkRow {
+                interval_start: interval.start,
+                interval_end: interval.get_end(),
+                vehicle_distance: interval.totals.vehicle_distance,
+                vehicle_hours: interval.totals.vehicle_seconds / 3600.0,
+                completed_trips: interval.totals.completed_trips,
+            })
+            .collect()
+    }
+
+    pub fn write_lanes_csv(&self, path: impl AsRef<Path>) -> Result<(), OutputError> {
+        results::write_csv(path, self.get_lane_rows())
+    }
+
+    pub fn write_network_csv(&self, path: impl AsRef<Path>) -> Result<(), OutputError> {
+        results::write_csv(path, self.get_network_rows())
+    }
+
+    /// Both tables, as `{"lanes": [...], "network": [...]}`
+    pub fn write_json(&self, path: impl AsRef<Path>) -> Result<(), OutputError> {
+        let report = Report { lanes: self.get_lane_rows(), network: self.get_network_rows() };
+        let file = File::create(path).map_err(OutputError::Io)?;
+        serde_json::to_writer_pretty(file, &report).map_err(OutputError::Json)
+    }
+}
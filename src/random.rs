@@ -0,0 +1,60 @@
+use std::collections::BTreeMap;
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::actor::ActorId;
+
+pub type SimRng = ChaCha8Rng;
+
+/// What a stream of random numbers is for. Streams are independent, so drawing more or
+/// fewer numbers from one never changes what another draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Stream {
+    /// building the network and placing actors before the run
+    Scenario,
+    Demand,
+    /// anything an actor decides for itself
+    Actor(ActorId),
+}
+
+impl Stream {
+    fn get_number(self) -> u64 {
+        match self {
+            Stream::Scenario => 0,
+            Stream::Demand => 1,
+            // well clear of the subsystems
+            Stream::Actor(id) => (1 << 32) + u64::from(id),
+        }
+    }
+}
+
+/// The same numbers for the same seed and stream, every run
+pub fn new_rng(seed: u64, stream: Stream) -> SimRng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(stream.get_number());
+    rng
+}
+
+/// Every stream for one seed, each created on first use
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rngs {
+    seed: u64,
+    streams: BTreeMap<Stream, SimRng>,
+}
+
+impl Rngs {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, streams: BTreeMap::new() }
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn get(&mut self, stream: Stream) -> &mut SimRng {
+        let seed = self.seed;
+        self.streams.entry(stream).or_insert_with(|| new_rng(seed, stream))
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actor::{ActorContext, ActorId},
+    error::ReplayError,
+    road::{
+        Direction, JunctionContext, JunctionLaneContext, Network, SegmentContext,
+        SegmentLaneContext,
+    },
+    simulate::Simulation,
+    spatial::{PointLike, Pos},
+    util::CloneEmpty,
+    vehicle::VehicleClass,
+};
+
+/// Bumped whenever what's written changes shape
+const REPLAY_VERSION: u32 = 1;
+
+/// What's drawn of an actor
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ActorState {
+    pub id: ActorId,
+    pub class: VehicleClass,
+    pub pos: Pos,
+    pub radius_visual: f64,
+    /// distance per second
+    pub speed: f64,
+}
+
+/// Every actor as of the start of a step
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    pub step: u64,
+    /// seconds since the start of the simulation
+    pub time: f64,
+    pub actors: Vec<ActorState>,
+}
+
+/// Comes first in a replay, before the frames
+#[derive(Serialize, Deserialize)]
+struct Header {
+    /// as of the first frame, without actors
+    network: Network,
+    connected: bool,
+}
+
+impl Frame {
+    pub fn capture(network: &Network, step: u64, time: f64) -> Self {
+        let mut actors = Vec::new();
+        let mut push = |actor_ctx: &ActorContext| {
+            let pos = match actor_ctx.get_pos() {
+                Ok(pos) => pos,
+                // somewhere with no geometry, which `Network::validate` reports
+                Err(_) => return,
+            };
+            let actor = actor_ctx.get_actor();
+            let vehicle = actor.get_vehicle();
+            actors.push(ActorState {
+                id: actor.get_id(),
+                class: vehicle.class,
+                pos,
+                radius_visual: vehicle.radius_visual,
+                speed: actor.get_speed(),
+            });
+        };
+        for (segment_id, segment) in network.segments.enumerate() {
+            let segment_ctx = &SegmentContext::new(network, segment_id, segment);
+            for (segment_side, actors) in [
+                (Direction::Forward, &segment.forward_actors),
+                (Direction::Backward, &segment.backward_actors),
+            ] {
+                for (pos_param, actor) in actors.enumerate() {
+                    push(&ActorContext::OffRoad {
+                        pos_param: *pos_param,
+                        segment_ctx,
+                        segment_side,
+                        actor,
+                    });
+                }
+            }
+            for direction in [Direction::Forward, Direction::Backward] {
+                for (rank, lane) in segment.get_lanes(direction).enumerate() {
+                    let lane_ctx = &SegmentLaneContext::new(segment_ctx, direction, rank, lane);
+                    for (pos_param, actor) in lane.actors.enumerate() {
+                        push(&ActorContext::OnRoadSegment {
+                            pos_param: *pos_param,
+                            lane_ctx,
+                            actor,
+                        });
+                    }
+                }
+            }
+        }
+        for (junction_id, junction) in network.junctions.enumerate() {
+            let junction_ctx = &JunctionContext::new(network, junction_id, junction);
+            for (lane_id, lane) in junction.enumerate_lanes() {
+                let lane_ctx = &JunctionLaneContext::new(junction_ctx, lane_id, lane);
+                for (pos_param, actor) in lane.actors.enumerate() {
+                    push(&ActorContext::OnRoadJunction { pos_param: *pos_param, lane_ctx, actor });
+                }
+            }
+        }
+        Self { step, time, actors }
+    }
+}
+
+/// Writes a frame per step, as an observer, so a run can be rendered again later without
+/// simulating it again. The network is written once, so later edits to it aren't recorded.
+pub struct ReplayWriter {
+    writer: BufWriter<File>,
+    header_written: bool,
+}
+
+impl ReplayWriter {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        let file = File::create(path).map_err(ReplayError::Io)?;
+        Ok(Self { writer: BufWriter::new(file), header_written: false })
+    }
+
+    pub fn record(&mut self, sim: &Simulation) -> Result<(), ReplayError> {
+        let network = sim.get_network();
+        if !self.header_written {
+            let header =
+                Header { network: network.clone_empty(), connected: network.is_connected() };
+            self.write(&REPLAY_VERSION)?;
+            self.write(&header)?;
+            self.header_written = true;
+        }
+        self.write(&Frame::capture(network, sim.get_step_count(), sim.get_time()))
+    }
+
+    fn write(&mut self, value: &impl Serialize) -> Result<(), ReplayError> {
+        bincode::serialize_into(&mut self.writer, value).map_err(ReplayError::Encoding)
+    }
+}
+
+/// Reads back what `ReplayWriter` wrote
+pub struct ReplayReader {
+    reader: BufReader<File>,
+    network: Network,
+}
+
+impl ReplayReader {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        let mut reader = BufReader::new(File::open(path).map_err(ReplayError::Io)?);
+        let version: u32 = bincode::deserialize_from(&mut reader).map_err(ReplayError::Encoding)?;
+        if version != REPLAY_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let header: Header =
+            bincode::deserialize_from(&mut reader).map_err(ReplayError::Encoding)?;
+        let mut network = header.network;
+        // derived from the rest, so not written
+        if header.connected {
+            network.rebuild_geometry_cache();
+        }
+        Ok(Self { reader, network })
+    }
+
+    /// Without actors; they're in the frames
+    pub fn get_network(&self) -> &Network {
+        &self.network
+    }
+
+    /// `None` once there are no more. A run cut short may leave a partial frame at the end,
+    /// which is taken to be the end too.
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, ReplayError> {
+        match bincode::deserialize_from(&mut self.reader) {
+            Ok(frame) => Ok(Some(frame)),
+            Err(e) => match *e {
+                bincode::ErrorKind::Io(ref io_error)
+                    if io_error.kind() == ErrorKind::UnexpectedEof =>
+                {
+                    Ok(None)
+                }
+                _ => Err(ReplayError::Encoding(e)),
+            },
+        }
+    }
+}
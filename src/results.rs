@@ -0,0 +1,158 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actor::{ActorId, LocationOffRoad},
+    error::{OutputError, RoutieError},
+    road::{PosParam, QualifiedSegmentLaneRank},
+    vehicle::VehicleClass,
+};
+
+/// A finished `TravelTo` agendum
+#[derive(Debug, Clone, PartialEq)]
+pub struct TripRecord {
+    pub vehicle_class: VehicleClass,
+    pub origin: LocationOffRoad,
+    /// as given in the agendum
+    pub destination: LocationOffRoad,
+    /// seconds since the start of the simulation
+    pub depart_time: f64,
+    pub arrive_time: f64,
+    /// along segment and junction lanes
+    pub distance: f64,
+    /// segment lanes, in the order they were driven
+    pub route: Vec<QualifiedSegmentLaneRank>,
+}
+
+impl TripRecord {
+    pub fn get_duration(&self) -> f64 {
+        self.arrive_time - self.depart_time
+    }
+}
+
+/// An actor moving along a segment lane during one step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LaneMove {
+    pub lane: QualifiedSegmentLaneRank,
+    pub from: PosParam,
+    /// past 1 if it carried on into a junction
+    pub to: PosParam,
+    /// distance per second, at the end of the step
+    pub speed: f64,
+    pub vehicle_length: f64,
+}
+
+impl LaneMove {
+    /// Whether the front of the vehicle passes `pos_param`
+    pub fn passes(&self, pos_param: PosParam) -> bool {
+        self.from < pos_param && pos_param <= self.to
+    }
+
+    /// Fraction of the step during which some of the vehicle was over `pos_param`,
+    /// taking its speed to be steady
+    pub fn get_fraction_over(&self, pos_param: PosParam, lane_length: f64) -> f64 {
+        // the front is between these while it's over
+        let (begin, end) = (pos_param, pos_param + self.vehicle_length / lane_length);
+        if self.to <= self.from {
+            return if begin <= self.from && self.from <= end { 1.0 } else { 0.0 };
+        }
+        (self.to.min(end) - self.from.max(begin)).max(0.0) / (self.to - self.from)
+    }
+}
+
+/// What happened during one step, besides the network changing
+#[derive(Debug, Default)]
+pub struct StepEvents {
+    pub trips: Vec<TripRecord>,
+    /// segment lanes left through their far end, once per actor leaving
+    pub lane_exits: Vec<QualifiedSegmentLaneRank>,
+    pub lane_moves: Vec<LaneMove>,
+    /// covered by all actors together, along lanes
+    pub distance: f64,
+    /// actors that couldn't be advanced, e.g. for lack of a route, and were taken off the
+    /// network so that the others can carry on
+    pub dropped_actors: Vec<(ActorId, RoutieError)>,
+}
+
+impl StepEvents {
+    /// Adds `other`'s events after these
+    pub fn append(&mut self, mut other: StepEvents) {
+        self.trips.append(&mut other.trips);
+        self.lane_exits.append(&mut other.lane_exits);
+        self.lane_moves.append(&mut other.lane_moves);
+        self.distance += other.distance;
+        self.dropped_actors.append(&mut other.dropped_actors);
+    }
+}
+
+/// Something that keeps simulation results, e.g. to summarize or write them out
+pub trait ResultsCollector {
+    fn record_trip(&mut self, trip: &TripRecord);
+
+    /// An actor taken off the network, see `StepEvents::dropped_actors`
+    fn record_dropped_actor(&mut self, _id: ActorId, _error: &RoutieError) {}
+}
+
+impl<F: FnMut(&TripRecord)> ResultsCollector for F {
+    fn record_trip(&mut self, trip: &TripRecord) {
+        self(trip)
+    }
+}
+
+/// Totals of some kind, one per fixed interval of simulated time
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Intervals<T> {
+    /// seconds
+    length: f64,
+    intervals: Vec<Interval<T>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interval<T> {
+    pub start: f64,
+    /// as stepped so far, so the last interval may be short
+    pub duration: f64,
+    pub totals: T,
+}
+
+impl<T> Interval<T> {
+    pub fn get_end(&self) -> f64 {
+        self.start + self.duration
+    }
+}
+
+impl<T: Default> Intervals<T> {
+    /// `length` is in seconds of simulated time
+    pub fn new(length: f64) -> Self {
+        Self { length, intervals: Vec::new() }
+    }
+
+    /// The interval the step starting at `time` falls in, begun if it's the first step in it,
+    /// with the step counted towards its duration
+    pub fn record_step(&mut self, time: f64, time_step: f64) -> &mut Interval<T> {
+        let start = (time / self.length).floor() * self.length;
+        if self.intervals.last().is_none_or(|interval| interval.start < start) {
+            self.intervals.push(Interval { start, duration: 0.0, totals: T::default() });
+        }
+        let interval = self.intervals.last_mut().unwrap();
+        interval.duration += time_step;
+        interval
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Interval<T>> {
+        self.intervals.iter()
+    }
+}
+
+/// With a header row taken from the field names
+pub fn write_csv(
+    path: impl AsRef<Path>,
+    rows: impl IntoIterator<Item = impl Serialize>,
+) -> Result<(), OutputError> {
+    let mut writer = csv::Writer::from_path(path).map_err(OutputError::Csv)?;
+    for row in rows {
+        writer.serialize(row).map_err(OutputError::Csv)?;
+    }
+    writer.flush().map_err(OutputError::Io)
+}
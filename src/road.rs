@@ -1,15 +1,27 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    f64::consts::PI,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use nalgebra::Vector2;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    actor::{self, Actor},
+    actor::{self, Actor, ActorId},
+    config::SimConfig,
     error::RoutieError,
-    spatial::Pos,
+    spatial::{GeometryCache, Pos},
     util::{ordered_skip_map::OrderedSkipMap, seq_indexed_store::SeqIndexedStore, CloneEmpty},
+    vehicle::VehicleParams,
 };
 
 pub type PosParam = f64;
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum Direction {
     Forward,
     Backward,
@@ -17,6 +29,24 @@ pub enum Direction {
 
 use Direction::{Backward, Forward};
 
+/// Where `Network::connect_junctions` should let actors turn back onto the segment they came from
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UTurnPolicy {
+    Never,
+    /// only at junctions with a single linked segment, i.e. cul-de-sacs
+    DeadEnds,
+    Everywhere,
+}
+
+/// Sets a segment's default speed limit, via `SimConfig::speed_limits`
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoadClass {
+    Motorway,
+    Arterial,
+    Residential,
+}
+
 define_index_type!(JunctionId);
 define_index_type!(SegmentId);
 define_index_type!(SegmentLaneRank);
@@ -24,82 +54,401 @@ define_index_type!(JunctionLaneId);
 
 pub type QualifiedSegmentLaneRank = (SegmentId, Direction, SegmentLaneRank);
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Network {
     pub junctions: SeqIndexedStore<JunctionId, Junction>,
     pub segments: SeqIndexedStore<SegmentId, Segment>,
-    junction_segments: HashMap<JunctionId, HashSet<SegmentId>>,
+    /// ordered, so that `connect_junctions` assigns the same lane IDs every run
+    junction_segments: HashMap<JunctionId, BTreeSet<SegmentId>>,
     segment_junctions: HashMap<SegmentId, (JunctionId, JunctionId)>,
+    /// shared with `clone_empty` copies, like the geometry cache
+    config: Arc<SimConfig>,
+    next_actor_id: ActorId,
+    /// shared with `clone_empty` copies, since their geometry is identical
+    #[serde(skip)]
+    geometry_cache: Option<Arc<GeometryCache>>,
+    /// Replaced on every edit other than to actors, with a value no network has had before,
+    /// so two networks with the same version have the same topology. Copied by `clone_empty`.
+    #[serde(skip, default = "new_topology_version")]
+    topology_version: u64,
 }
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Junction {
     pub pos: Pos,
+    /// overrides the footprint computed from the linked segments
+    radius: Option<f64>,
+    /// traffic from any other segment yields to traffic from these
+    priority_inputs: HashSet<SegmentId>,
     pub lanes: SeqIndexedStore<JunctionLaneId, JunctionLane>,
-    lane_inputs: HashMap<QualifiedSegmentLaneRank, HashSet<JunctionLaneId>>,
+    /// ordered, so that routes are the same every run when costs tie
+    lane_inputs: HashMap<QualifiedSegmentLaneRank, BTreeSet<JunctionLaneId>>,
     lane_inputs_inverse: HashMap<JunctionLaneId, QualifiedSegmentLaneRank>,
     lane_outputs: HashMap<JunctionLaneId, QualifiedSegmentLaneRank>,
 }
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct JunctionLane {
+    #[serde(with = "actors_serde")]
     pub actors: OrderedSkipMap<PosParam, Actor>,
 }
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Segment {
+    /// intermediate points between the begin and end junctions, in order
+    shape: Vec<Pos>,
+    class: RoadClass,
+    /// distance per second; overrides the limit for `class`
+    speed_limit: Option<f64>,
     pub forward_lanes: SeqIndexedStore<SegmentLaneRank, SegmentLane>,
     pub backward_lanes: SeqIndexedStore<SegmentLaneRank, SegmentLane>,
     /// off-road only, otherwise they belong to lanes
+    #[serde(with = "actors_serde")]
     pub forward_actors: OrderedSkipMap<PosParam, Actor>,
+    #[serde(with = "actors_serde")]
     pub backward_actors: OrderedSkipMap<PosParam, Actor>,
 }
-#[derive(Debug)]
+/// Actors placed during a step, by where they go, so that parts of the network can be
+/// stepped separately and merged into the next network with `Network::insert_actors`.
+/// Actors in the same place keep the order they were pushed in.
+#[derive(Debug, Default)]
+pub struct ActorInsertions {
+    segment_lanes: BTreeMap<QualifiedSegmentLaneRank, Vec<(PosParam, Actor)>>,
+    junction_lanes: BTreeMap<(JunctionId, JunctionLaneId), Vec<(PosParam, Actor)>>,
+    /// by segment side
+    off_road: BTreeMap<(SegmentId, Direction), Vec<(PosParam, Actor)>>,
+}
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SegmentLane {
     pub direction: Direction, // TODO: remove this; it belongs to the context
+    #[serde(with = "actors_serde")]
     pub actors: OrderedSkipMap<PosParam, Actor>,
 }
 
 impl Network {
     pub fn new() -> Self {
+        Self::with_config(SimConfig::default())
+    }
+
+    pub fn with_config(config: SimConfig) -> Self {
         Self {
-            junctions: SeqIndexedStore::new(),
-            segments: SeqIndexedStore::new(),
+            junctions: SeqIndexedStore::new_reusing_slots(),
+            segments: SeqIndexedStore::new_reusing_slots(),
             junction_segments: HashMap::new(),
             segment_junctions: HashMap::new(),
+            config: Arc::new(config),
+            next_actor_id: ActorId(0),
+            geometry_cache: None,
+            topology_version: new_topology_version(),
+        }
+    }
+
+    pub fn get_config(&self) -> &SimConfig {
+        &self.config
+    }
+
+    /// Geometry depends on the config, so the cache is rebuilt if there is one
+    pub fn set_config(&mut self, config: SimConfig) {
+        let was_connected = self.geometry_cache.is_some();
+        self.config = Arc::new(config);
+        self.invalidate_geometry_cache();
+        if was_connected {
+            self.rebuild_geometry_cache();
         }
     }
 
     pub fn add_junction(&mut self, pos: Pos) -> JunctionId {
+        self.invalidate_geometry_cache();
         self.junctions.push(Junction::new(pos))
     }
 
+    pub fn set_junction_radius(
+        &mut self,
+        junction_id: JunctionId,
+        radius: f64,
+    ) -> Result<(), RoutieError> {
+        self.invalidate_geometry_cache();
+        let junction = self.get_junction_mut(junction_id)?;
+        junction.radius = Some(radius);
+        Ok(())
+    }
+
+    pub fn set_priority_input(
+        &mut self,
+        junction_id: JunctionId,
+        segment_id: SegmentId,
+    ) -> Result<(), RoutieError> {
+        self.get_segment(segment_id)?;
+        let junction = self.get_junction_mut_untracked(junction_id)?;
+        junction.priority_inputs.insert(segment_id);
+        // who yields changes, but not the geometry
+        self.topology_version = new_topology_version();
+        Ok(())
+    }
+
+    /// Replace a junction with a ring of one-way segments, one ring junction per linked
+    /// segment, where circulating traffic has priority over entering traffic.
+    /// `junction_id` is kept as one of the ring junctions. Returns the ring junctions,
+    /// in the order traffic visits them. `connect_junctions` must be (re-)run afterwards.
+    pub fn expand_into_roundabout(
+        &mut self,
+        junction_id: JunctionId,
+        radius: f64,
+        ring_lane_count: usize,
+    ) -> Result<Vec<JunctionId>, RoutieError> {
+        let center = self.get_junction(junction_id)?.pos;
+
+        // arms, by angle of the linked segment as it leaves the junction
+        let mut arms: Vec<(f64, SegmentId)> = self
+            .get_junction_segments(junction_id)
+            .map(|segment_id| {
+                let (begin_id, end_id) = self.segment_junctions[&segment_id];
+                let shape = self.segments.get(&segment_id).unwrap().get_shape();
+                let toward = if begin_id == junction_id {
+                    shape.first().copied().unwrap_or(self.junctions.get(&end_id).unwrap().pos)
+                } else {
+                    shape.last().copied().unwrap_or(self.junctions.get(&begin_id).unwrap().pos)
+                };
+                ((toward.y - center.y).atan2(toward.x - center.x), segment_id)
+            })
+            .collect();
+        if arms.len() < 2 {
+            return Err(RoutieError::TooFewLinkedSegments(junction_id));
+        }
+        arms.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        // relink each arm to its own ring junction
+        let mut ring = Vec::new();
+        for (idx, (angle, segment_id)) in arms.iter().enumerate() {
+            let pos = center + radius * Vector2::new(angle.cos(), angle.sin());
+            let ring_junction_id = if idx == 0 {
+                self.junctions.get_mut(&junction_id).unwrap().pos = pos;
+                junction_id
+            } else {
+                self.add_junction(pos)
+            };
+            self.junction_segments.get_mut(&junction_id).unwrap().remove(segment_id);
+            self.junction_segments.entry(ring_junction_id).or_default().insert(*segment_id);
+            let (begin_id, end_id) = self.segment_junctions.get_mut(segment_id).unwrap();
+            for id in [begin_id, end_id] {
+                if *id == junction_id {
+                    *id = ring_junction_id;
+                }
+            }
+            ring.push((*angle, ring_junction_id));
+        }
+
+        // counterclockwise on screen (y points down), for right-hand traffic
+        ring.reverse();
+        let shape_points = self.config.roundabout_shape_points;
+        for idx in 0..ring.len() {
+            let (begin_angle, begin_id) = ring[idx];
+            let (end_angle, end_id) = ring[(idx + 1) % ring.len()];
+            let sweep = (begin_angle - end_angle).rem_euclid(2.0 * PI);
+            let shape = (1..shape_points)
+                .map(|step| {
+                    let angle = begin_angle - sweep * (step as f64 / shape_points as f64);
+                    center + radius * Vector2::new(angle.cos(), angle.sin())
+                })
+                .collect();
+            let (segment_id, segment) = self.add_segment(begin_id, end_id)?;
+            segment.set_shape(shape);
+            for _ in 0..ring_lane_count {
+                segment.add_lane(Forward);
+            }
+            self.set_priority_input(end_id, segment_id)?;
+        }
+        self.invalidate_geometry_cache();
+        Ok(ring.into_iter().map(|(_, id)| id).collect())
+    }
+
     pub fn add_segment(
         &mut self,
         begin_id: JunctionId,
         end_id: JunctionId,
-    ) -> (SegmentId, &mut Segment) {
+    ) -> Result<(SegmentId, &mut Segment), RoutieError> {
+        self.get_junction(begin_id)?;
+        self.get_junction(end_id)?;
+        self.invalidate_geometry_cache();
         let id = self.segments.push(Segment::new());
         self.segment_junctions.insert(id, (begin_id, end_id));
         for junction in [begin_id, end_id].iter() {
-            if !self.junction_segments.entry(*junction).or_insert(HashSet::new()).insert(id) {
+            if !self.junction_segments.entry(*junction).or_default().insert(id) {
                 log::warn!("Segment loops! Is this what you want?");
             };
         }
         let segment = self.segments.get_mut(&id).unwrap();
-        (id, segment)
+        Ok((id, segment))
+    }
+
+    /// Places a new actor off-road
+    pub fn add_actor(
+        &mut self,
+        segment_id: SegmentId,
+        pos_param: PosParam,
+        direction: Direction,
+        vehicle: VehicleParams,
+        agenda: Vec<actor::Agendum>,
+    ) -> Result<ActorId, RoutieError> {
+        let id = self.next_actor_id;
+        let actor = Actor::new(id, self.config.seed, vehicle, agenda);
+        self.get_segment_mut_untracked(segment_id)?.add_actor(pos_param, direction, actor);
+        self.next_actor_id = ActorId(id.0 + 1);
+        Ok(id)
+    }
+
+    pub fn get_junction(&self, id: JunctionId) -> Result<&Junction, RoutieError> {
+        self.junctions.try_get(&id).map_err(|e| e.or_unknown(RoutieError::UnknownJunction(id)))
+    }
+
+    /// Counts as an edit, since the junction may be moved, so the geometry cache is dropped
+    /// until `connect_junctions` runs again
+    pub fn get_junction_mut(&mut self, id: JunctionId) -> Result<&mut Junction, RoutieError> {
+        self.invalidate_geometry_cache();
+        self.get_junction_mut_untracked(id)
+    }
+
+    /// For placing actors, which leaves the topology as it is
+    fn get_junction_mut_untracked(&mut self, id: JunctionId) -> Result<&mut Junction, RoutieError> {
+        self.junctions.try_get_mut(&id).map_err(|e| e.or_unknown(RoutieError::UnknownJunction(id)))
+    }
+
+    pub fn get_segment(&self, id: SegmentId) -> Result<&Segment, RoutieError> {
+        self.segments.try_get(&id).map_err(|e| e.or_unknown(RoutieError::UnknownSegment(id)))
+    }
+
+    /// Counts as an edit, as `get_junction_mut` does
+    pub fn get_segment_mut(&mut self, id: SegmentId) -> Result<&mut Segment, RoutieError> {
+        self.invalidate_geometry_cache();
+        self.get_segment_mut_untracked(id)
+    }
+
+    /// For placing actors, which leaves the topology as it is
+    fn get_segment_mut_untracked(&mut self, id: SegmentId) -> Result<&mut Segment, RoutieError> {
+        self.segments.try_get_mut(&id).map_err(|e| e.or_unknown(RoutieError::UnknownSegment(id)))
+    }
+
+    pub fn get_segment_lane(
+        &self,
+        lane @ (segment_id, direction, rank): QualifiedSegmentLaneRank,
+    ) -> Result<&SegmentLane, RoutieError> {
+        self.get_segment(segment_id)?
+            .get_lanes(direction)
+            .try_get(&rank)
+            .map_err(|e| e.or_unknown(RoutieError::UnknownSegmentLane(lane)))
+    }
+
+    pub fn get_segment_lane_mut(
+        &mut self,
+        lane @ (segment_id, direction, rank): QualifiedSegmentLaneRank,
+    ) -> Result<&mut SegmentLane, RoutieError> {
+        self.get_segment_mut(segment_id)?
+            .get_lanes_mut(direction)
+            .try_get_mut(&rank)
+            .map_err(|e| e.or_unknown(RoutieError::UnknownSegmentLane(lane)))
+    }
+
+    pub fn get_segment_lane_length(
+        &self,
+        lane @ (segment_id, direction, rank): QualifiedSegmentLaneRank,
+    ) -> Result<f64, RoutieError> {
+        let segment_ctx = &SegmentContext::new(self, segment_id, self.get_segment(segment_id)?);
+        let lane = self.get_segment_lane(lane)?;
+        SegmentLaneContext::new(segment_ctx, direction, rank, lane).get_length()
+    }
+
+    pub fn get_junction_lane_length(
+        &self,
+        junction_id: JunctionId,
+        lane_id: JunctionLaneId,
+    ) -> Result<f64, RoutieError> {
+        let junction = self.get_junction(junction_id)?;
+        let junction_ctx = &JunctionContext::new(self, junction_id, junction);
+        let lane = junction.lanes.get(&lane_id).ok_or(RoutieError::UnknownJunctionLane(lane_id))?;
+        JunctionLaneContext::new(junction_ctx, lane_id, lane).get_length()
+    }
+
+    /// Actors in the same place are kept in `ActorId` order
+    pub fn insert_actors(&mut self, insertions: ActorInsertions) -> Result<(), RoutieError> {
+        for (lane @ (segment_id, direction, rank), actors) in insertions.segment_lanes {
+            let lane = self
+                .get_segment_mut_untracked(segment_id)?
+                .get_lanes_mut(direction)
+                .try_get_mut(&rank)
+                .map_err(|e| e.or_unknown(RoutieError::UnknownSegmentLane(lane)))?;
+            for (pos_param, actor) in actors {
+                lane.actors.insert(pos_param, actor);
+            }
+        }
+        for ((junction_id, lane_id), actors) in insertions.junction_lanes {
+            let lane = self
+                .get_junction_mut_untracked(junction_id)?
+                .lanes
+                .get_mut(&lane_id)
+                .ok_or(RoutieError::UnknownJunctionLane(lane_id))?;
+            for (pos_param, actor) in actors {
+                lane.actors.insert(pos_param, actor);
+            }
+        }
+        for ((segment_id, direction), actors) in insertions.off_road {
+            let segment = self.get_segment_mut_untracked(segment_id)?;
+            for (pos_param, actor) in actors {
+                segment.add_actor(pos_param, direction, actor);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_geometry_cache(&self) -> Option<&GeometryCache> {
+        self.geometry_cache.as_deref()
+    }
+
+    /// Must be called after editing `junctions` or `segments` directly.
+    /// `connect_junctions` rebuilds the cache.
+    pub fn invalidate_geometry_cache(&mut self) {
+        self.geometry_cache = None;
+        self.topology_version = new_topology_version();
+    }
+
+    /// Changes whenever anything but actors does, e.g. for caches derived from the topology
+    pub fn get_topology_version(&self) -> u64 {
+        self.topology_version
     }
 
     pub fn get_segment_junctions(
         &self,
         segment: SegmentId,
     ) -> Result<(JunctionId, JunctionId), RoutieError> {
+        self.get_segment(segment)?;
         match self.segment_junctions.get(&segment) {
-            None => Err(RoutieError::InvalidId),
+            None => Err(RoutieError::UnknownSegment(segment)),
             Some((begin_id, end_id)) => Ok((*begin_id, *end_id)),
         }
     }
 
-    pub fn connect_junctions(&mut self) {
+    pub fn get_junction_segments(
+        &self,
+        junction: JunctionId,
+    ) -> impl Iterator<Item = SegmentId> + '_ {
+        self.junction_segments.get(&junction).into_iter().flatten().copied()
+    }
+
+    pub fn get_lane_end_junction(
+        &self,
+        (segment_id, direction, _): QualifiedSegmentLaneRank,
+    ) -> Result<JunctionId, RoutieError> {
+        let (begin_id, end_id) = self.get_segment_junctions(segment_id)?;
+        Ok(match direction {
+            Forward => end_id,
+            Backward => begin_id,
+        })
+    }
+
+    /// Links the lanes of the segments meeting at each junction. Can be re-run after editing:
+    /// junction lanes that still link the same segment lanes are kept, with their IDs and
+    /// actors, and the rest are removed.
+    pub fn connect_junctions(&mut self, u_turn_policy: UTurnPolicy) {
+        self.invalidate_geometry_cache();
         for (junction_id, junction) in self.junctions.enumerate_mut() {
-            let empty_set = HashSet::<SegmentId>::new();
+            let mut links = Vec::new();
+            let empty_set = BTreeSet::<SegmentId>::new();
             let segment_ids = match self.junction_segments.get(&junction_id) {
                 Some(ids) => ids,
                 None => {
@@ -107,11 +456,16 @@ impl Network {
                     &empty_set
                 }
             };
+            let allow_u_turns = match u_turn_policy {
+                UTurnPolicy::Never => false,
+                UTurnPolicy::DeadEnds => segment_ids.len() == 1,
+                UTurnPolicy::Everywhere => true,
+            };
             for incoming_segment_id in segment_ids {
                 let incoming_segment = self.segments.get(incoming_segment_id).unwrap();
                 let incoming_direction = {
                     let (begin_junction_id, end_junction_id) =
-                        *self.segment_junctions.get(&incoming_segment_id).unwrap();
+                        *self.segment_junctions.get(incoming_segment_id).unwrap();
                     assert!(junction_id == begin_junction_id || junction_id == end_junction_id);
                     // damn you rustfmt
                     if junction_id == begin_junction_id {
@@ -123,13 +477,13 @@ impl Network {
 
                 let incoming_lanes = incoming_segment.get_lanes(incoming_direction);
                 for outgoing_segment_id in segment_ids {
-                    if incoming_segment_id == outgoing_segment_id {
+                    if incoming_segment_id == outgoing_segment_id && !allow_u_turns {
                         continue;
                     }
                     let outgoing_segment = self.segments.get(outgoing_segment_id).unwrap();
                     let outgoing_direction = {
                         let (begin_junction_id, end_junction_id) =
-                            *self.segment_junctions.get(&outgoing_segment_id).unwrap();
+                            *self.segment_junctions.get(outgoing_segment_id).unwrap();
                         assert!(junction_id == begin_junction_id || junction_id == end_junction_id);
                         if junction_id == begin_junction_id {
                             Forward
@@ -143,95 +497,523 @@ impl Network {
                     for ((incoming_lane_rank, _), (outgoing_lane_rank, _)) in
                         std::iter::zip(incoming_lanes.enumerate(), outgoing_lanes.enumerate())
                     {
-                        junction.add_lane(
+                        links.push((
                             (*incoming_segment_id, incoming_direction, incoming_lane_rank),
                             (*outgoing_segment_id, outgoing_direction, outgoing_lane_rank),
-                        );
+                        ));
                     }
                 }
             }
+            let wanted: HashSet<_> = links.iter().copied().collect();
+            junction.retain_lanes(|input, output| wanted.contains(&(input, output)));
+            for (input, output) in links {
+                if !junction.has_lane(input, output) {
+                    junction.add_lane(input, output);
+                }
+            }
+        }
+        self.rebuild_geometry_cache();
+    }
+
+    /// Whether `connect_junctions` has run since the last edit
+    pub fn is_connected(&self) -> bool {
+        self.geometry_cache.is_some()
+    }
+
+    pub(crate) fn rebuild_geometry_cache(&mut self) {
+        self.geometry_cache = Some(Arc::new(GeometryCache::build(self)));
+    }
+
+    /// As `clone_empty`, but reuses `spare`'s lanes and actor stores when its topology version
+    /// is the same, so that stepping allocates nothing but actors. `spare` is typically the
+    /// network this one was advanced from.
+    pub fn clone_empty_reusing(&self, mut spare: Network) -> Network {
+        if spare.topology_version != self.topology_version {
+            return self.clone_empty();
+        }
+        spare.clear_actors();
+        spare.next_actor_id = self.next_actor_id;
+        spare
+    }
+
+    fn clear_actors(&mut self) {
+        for (_, segment) in self.segments.enumerate_mut() {
+            segment.forward_actors.clear();
+            segment.backward_actors.clear();
+            for direction in [Direction::Forward, Direction::Backward] {
+                for (_, lane) in segment.get_lanes_mut(direction).enumerate_mut() {
+                    lane.actors.clear();
+                }
+            }
+        }
+        for (_, junction) in self.junctions.enumerate_mut() {
+            for (_, lane) in junction.lanes.enumerate_mut() {
+                lane.actors.clear();
+            }
+        }
+    }
+
+    /// Removes a junction along with every segment linked to it, since segments can't dangle.
+    /// Returns the IDs of the removed segments. Other IDs are unaffected. Fails with `InUse`,
+    /// removing nothing, if an actor is on any of it or routed through it.
+    pub fn remove_junction(
+        &mut self,
+        junction_id: JunctionId,
+    ) -> Result<Vec<SegmentId>, RoutieError> {
+        self.get_junction(junction_id)?;
+        let segment_ids: Vec<SegmentId> = self.get_junction_segments(junction_id).collect();
+        let mut removal = Removal::default();
+        removal.junctions.insert(junction_id);
+        for segment_id in &segment_ids {
+            self.add_segment_to_removal(&mut removal, *segment_id)?;
+        }
+        self.check_unused(&removal)?;
+
+        let was_connected = self.geometry_cache.is_some();
+        for segment_id in &segment_ids {
+            self.unlink_segment(*segment_id);
+        }
+        self.junction_segments.remove(&junction_id);
+        self.junctions.remove(&junction_id);
+        self.finish_removal(was_connected);
+        Ok(segment_ids)
+    }
+
+    /// Removes a segment, its lanes and any junction lanes into or out of it. Fails with
+    /// `InUse`, removing nothing, if an actor is on any of it or routed through it.
+    pub fn remove_segment(&mut self, segment_id: SegmentId) -> Result<(), RoutieError> {
+        let mut removal = Removal::default();
+        self.add_segment_to_removal(&mut removal, segment_id)?;
+        self.check_unused(&removal)?;
+
+        let was_connected = self.geometry_cache.is_some();
+        self.unlink_segment(segment_id);
+        self.finish_removal(was_connected);
+        Ok(())
+    }
+
+    /// Removes a segment lane and any junction lanes into or out of it. Ranks of the
+    /// remaining lanes are unaffected. Fails with `InUse`, removing nothing, if an actor is
+    /// on any of it or routed through it.
+    pub fn remove_lane(&mut self, lane: QualifiedSegmentLaneRank) -> Result<(), RoutieError> {
+        let (segment_id, direction, rank) = lane;
+        self.get_segment_lane(lane)?;
+        let mut removal = Removal::default();
+        removal.segment_lanes.insert(lane);
+        self.add_junction_lanes_to_removal(&mut removal, segment_id, |segment_lane| {
+            segment_lane == lane
+        })?;
+        self.check_unused(&removal)?;
+
+        let was_connected = self.geometry_cache.is_some();
+        self.get_segment_mut(segment_id)?.get_lanes_mut(direction).remove(&rank);
+        for (junction_id, _) in &removal.junction_lanes {
+            if let Some(junction) = self.junctions.get_mut(junction_id) {
+                junction.remove_lanes_where(|segment_lane| segment_lane == lane);
+            }
+        }
+        self.finish_removal(was_connected);
+        Ok(())
+    }
+
+    fn add_segment_to_removal(
+        &self,
+        removal: &mut Removal,
+        segment_id: SegmentId,
+    ) -> Result<(), RoutieError> {
+        let segment = self.get_segment(segment_id)?;
+        removal.segments.insert(segment_id);
+        for direction in [Forward, Backward] {
+            for (rank, _) in segment.get_lanes(direction).enumerate() {
+                removal.segment_lanes.insert((segment_id, direction, rank));
+            }
+        }
+        self.add_junction_lanes_to_removal(removal, segment_id, |(id, _, _)| id == segment_id)
+    }
+
+    /// Junction lanes at either end of `segment_id` into or out of a segment lane matching
+    /// `predicate`
+    fn add_junction_lanes_to_removal(
+        &self,
+        removal: &mut Removal,
+        segment_id: SegmentId,
+        predicate: impl Fn(QualifiedSegmentLaneRank) -> bool,
+    ) -> Result<(), RoutieError> {
+        let (begin_id, end_id) = self.get_segment_junctions(segment_id)?;
+        for junction_id in [begin_id, end_id] {
+            let junction = self.get_junction(junction_id)?;
+            for (lane_id, _) in junction.enumerate_lanes() {
+                let (input, output) = junction.get_segment_lanes_for_junction_lane(lane_id)?;
+                if predicate(input) || predicate(output) {
+                    removal.junction_lanes.insert((junction_id, lane_id));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails with the first actor found on, routed through or headed for what's to be
+    /// removed, since it would be lost or stuck otherwise
+    fn check_unused(&self, removal: &Removal) -> Result<(), RoutieError> {
+        for (segment_id, segment) in self.segments.enumerate() {
+            for actors in [&segment.forward_actors, &segment.backward_actors] {
+                for (_, actor) in actors.enumerate() {
+                    if removal.segments.contains(&segment_id) || removal.is_headed_for(actor) {
+                        return Err(RoutieError::InUse(actor.get_id()));
+                    }
+                }
+            }
+            for direction in [Forward, Backward] {
+                for (rank, lane) in segment.get_lanes(direction).enumerate() {
+                    let segment_lane = (segment_id, direction, rank);
+                    for (_, actor) in lane.actors.enumerate() {
+                        if removal.segment_lanes.contains(&segment_lane)
+                            || removal.is_headed_for(actor)
+                            || self.is_routed_through(removal, segment_lane, actor)
+                        {
+                            return Err(RoutieError::InUse(actor.get_id()));
+                        }
+                    }
+                }
+            }
+        }
+        for (junction_id, junction) in self.junctions.enumerate() {
+            for (lane_id, lane) in junction.enumerate_lanes() {
+                let (_, output) = junction.get_segment_lanes_for_junction_lane(lane_id)?;
+                for (_, actor) in lane.actors.enumerate() {
+                    if removal.junctions.contains(&junction_id)
+                        || removal.junction_lanes.contains(&(junction_id, lane_id))
+                        || removal.is_headed_for(actor)
+                        || self.is_routed_through(removal, output, actor)
+                    {
+                        return Err(RoutieError::InUse(actor.get_id()));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the rest of `actor`'s route, driven from the end of `segment_lane`, uses
+    /// anything in `removal`. Junction lane IDs are per junction, so the route is followed
+    /// lane by lane to find the junction each turn is at.
+    fn is_routed_through(
+        &self,
+        removal: &Removal,
+        mut segment_lane: QualifiedSegmentLaneRank,
+        actor: &Actor,
+    ) -> bool {
+        for step in actor.get_route().iter().rev() {
+            if removal.segment_lanes.contains(&segment_lane) {
+                return true;
+            }
+            let lane_id = match step {
+                actor::RouteStep::TurnAt(lane_id) => *lane_id,
+                _ => break,
+            };
+            let junction_id = match self.get_lane_end_junction(segment_lane) {
+                Ok(junction_id) => junction_id,
+                Err(_) => break,
+            };
+            if removal.junction_lanes.contains(&(junction_id, lane_id)) {
+                return true;
+            }
+            segment_lane = match self
+                .get_junction(junction_id)
+                .and_then(|junction| junction.get_segment_lanes_for_junction_lane(lane_id))
+            {
+                Ok((_, output)) => output,
+                Err(_) => break,
+            };
+        }
+        removal.segment_lanes.contains(&segment_lane)
+    }
+
+    /// Unlinks the segment from its junctions and removes it, leaving the geometry cache be
+    fn unlink_segment(&mut self, segment_id: SegmentId) {
+        if let Some((begin_id, end_id)) = self.segment_junctions.remove(&segment_id) {
+            for junction_id in [begin_id, end_id] {
+                if let Some(segment_ids) = self.junction_segments.get_mut(&junction_id) {
+                    segment_ids.remove(&segment_id);
+                }
+                if let Some(junction) = self.junctions.get_mut(&junction_id) {
+                    junction.priority_inputs.remove(&segment_id);
+                    junction.remove_lanes_where(|(id, _, _)| id == segment_id);
+                }
+            }
+        }
+        self.segments.remove(&segment_id);
+    }
+
+    fn finish_removal(&mut self, was_connected: bool) {
+        self.invalidate_geometry_cache();
+        if was_connected {
+            self.rebuild_geometry_cache();
         }
     }
 }
+
+impl Default for Network {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+/// Everything a `remove_*` call is about to remove
+#[derive(Debug, Default)]
+struct Removal {
+    junctions: HashSet<JunctionId>,
+    segments: HashSet<SegmentId>,
+    segment_lanes: HashSet<QualifiedSegmentLaneRank>,
+    junction_lanes: HashSet<(JunctionId, JunctionLaneId)>,
+}
+impl Removal {
+    fn is_headed_for(&self, actor: &Actor) -> bool {
+        actor.get_agenda().iter().any(|agendum| match agendum {
+            actor::Agendum::TravelTo { segment_id, .. } => self.segments.contains(segment_id),
+            _ => false,
+        })
+    }
+}
 impl Junction {
     pub fn new(pos: Pos) -> Self {
         Self {
             pos,
-            lanes: SeqIndexedStore::new(),
+            radius: None,
+            priority_inputs: HashSet::new(),
+            lanes: SeqIndexedStore::new_reusing_slots(),
             lane_inputs: HashMap::new(),
             lane_inputs_inverse: HashMap::new(),
             lane_outputs: HashMap::new(),
         }
     }
 
+    pub fn get_radius(&self) -> Option<f64> {
+        self.radius
+    }
+
     fn add_lane(
         &mut self,
         begin: QualifiedSegmentLaneRank,
         end: QualifiedSegmentLaneRank,
     ) -> &JunctionLane {
         let id = self.lanes.push(JunctionLane::new());
-        self.lane_inputs.entry(begin).or_insert(HashSet::new()).insert(id);
+        self.lane_inputs.entry(begin).or_default().insert(id);
         self.lane_inputs_inverse.insert(id, begin);
         self.lane_outputs.insert(id, end);
         self.lanes.get(&id).unwrap()
     }
 
+    /// Whether an actor entering `lane_id` must wait for traffic already on a
+    /// conflicting lane, i.e. one from a priority input merging into the same segment
+    pub fn must_yield(&self, lane_id: JunctionLaneId) -> Result<bool, RoutieError> {
+        let ((input_segment_id, _, _), (output_segment_id, output_direction, _)) =
+            self.get_segment_lanes_for_junction_lane(lane_id)?;
+        if self.priority_inputs.is_empty() || self.priority_inputs.contains(&input_segment_id) {
+            return Ok(false);
+        }
+        Ok(self.lanes.enumerate().any(|(other_lane_id, other_lane)| {
+            match self.get_segment_lanes_for_junction_lane(other_lane_id) {
+                Ok(((other_input_id, _, _), (other_output_id, other_output_direction, _))) => {
+                    self.priority_inputs.contains(&other_input_id)
+                        && other_output_id == output_segment_id
+                        && other_output_direction == output_direction
+                        && !other_lane.actors.is_empty()
+                }
+                Err(_) => false,
+            }
+        }))
+    }
+
+    fn has_lane(&self, input: QualifiedSegmentLaneRank, output: QualifiedSegmentLaneRank) -> bool {
+        self.lane_inputs
+            .get(&input)
+            .is_some_and(|lane_ids| lane_ids.iter().any(|id| self.lane_outputs[id] == output))
+    }
+
+    /// Removes every lane whose input or output segment lane matches `predicate`
+    fn remove_lanes_where(&mut self, predicate: impl Fn(QualifiedSegmentLaneRank) -> bool) {
+        self.retain_lanes(|input, output| !predicate(input) && !predicate(output));
+    }
+
+    /// Keeps only the lanes for which `keep` holds, given their input and output segment lanes
+    fn retain_lanes(
+        &mut self,
+        keep: impl Fn(QualifiedSegmentLaneRank, QualifiedSegmentLaneRank) -> bool,
+    ) {
+        let lane_ids: Vec<JunctionLaneId> = self
+            .lanes
+            .enumerate()
+            .map(|(id, _)| id)
+            .filter(|id| !keep(self.lane_inputs_inverse[id], self.lane_outputs[id]))
+            .collect();
+        for id in lane_ids {
+            let input = self.lane_inputs_inverse.remove(&id).unwrap();
+            if let Some(lane_ids) = self.lane_inputs.get_mut(&input) {
+                lane_ids.remove(&id);
+                if lane_ids.is_empty() {
+                    self.lane_inputs.remove(&input);
+                }
+            }
+            self.lane_outputs.remove(&id);
+            self.lanes.remove(&id);
+        }
+    }
+
     pub fn enumerate_lanes(&self) -> impl Iterator<Item = (JunctionLaneId, &JunctionLane)> {
         self.lanes.enumerate()
     }
 
-    // TODO: bubble up errors
     pub fn get_segment_lanes_for_junction_lane(
         &self,
         lane_id: JunctionLaneId,
-    ) -> (QualifiedSegmentLaneRank, QualifiedSegmentLaneRank) {
-        let input_segment_lane = self.lane_inputs_inverse.get(&lane_id).unwrap();
-        let output_segment_lane = self.lane_outputs.get(&lane_id).unwrap();
-        (*input_segment_lane, *output_segment_lane)
+    ) -> Result<(QualifiedSegmentLaneRank, QualifiedSegmentLaneRank), RoutieError> {
+        match (self.lane_inputs_inverse.get(&lane_id), self.lane_outputs.get(&lane_id)) {
+            (Some(input_segment_lane), Some(output_segment_lane)) => {
+                Ok((*input_segment_lane, *output_segment_lane))
+            }
+            _ => Err(RoutieError::UnknownJunctionLane(lane_id)),
+        }
     }
 
-    // TODO: bubble up errors
+    /// Empty if no junction lane leads out of `input` here, whether because it's a dead end
+    /// or because `input` doesn't end at this junction
     pub fn get_outputs_for_input(
         &self,
         input: QualifiedSegmentLaneRank,
-    ) -> HashSet<QualifiedSegmentLaneRank> {
-        let junction_lanes = self.lane_inputs.get(&input).unwrap();
-        junction_lanes
-            .into_iter()
-            .map(|junction_lane| *self.lane_outputs.get(junction_lane).unwrap())
-            .collect()
+    ) -> BTreeSet<QualifiedSegmentLaneRank> {
+        match self.lane_inputs.get(&input) {
+            // dead end
+            None => BTreeSet::new(),
+            Some(junction_lanes) => junction_lanes
+                .iter()
+                .filter_map(|junction_lane| self.lane_outputs.get(junction_lane).copied())
+                .collect(),
+        }
+    }
+
+    pub fn get_lane_for_segment_lanes(
+        &self,
+        input: QualifiedSegmentLaneRank,
+        output: QualifiedSegmentLaneRank,
+    ) -> Option<JunctionLaneId> {
+        self.lane_inputs
+            .get(&input)?
+            .iter()
+            .find(|junction_lane| self.lane_outputs.get(junction_lane) == Some(&output))
+            .copied()
     }
 }
 
+impl ActorInsertions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn push_segment_lane(
+        &mut self,
+        lane: QualifiedSegmentLaneRank,
+        pos_param: PosParam,
+        actor: Actor,
+    ) {
+        self.segment_lanes.entry(lane).or_default().push((pos_param, actor));
+    }
+    pub fn push_junction_lane(
+        &mut self,
+        junction_id: JunctionId,
+        lane_id: JunctionLaneId,
+        pos_param: PosParam,
+        actor: Actor,
+    ) {
+        self.junction_lanes.entry((junction_id, lane_id)).or_default().push((pos_param, actor));
+    }
+    /// On the `segment_side` side of the segment
+    pub fn push_off_road(
+        &mut self,
+        segment_id: SegmentId,
+        segment_side: Direction,
+        pos_param: PosParam,
+        actor: Actor,
+    ) {
+        self.off_road.entry((segment_id, segment_side)).or_default().push((pos_param, actor));
+    }
+}
+
+fn new_topology_version() -> u64 {
+    static NEXT_TOPOLOGY_VERSION: AtomicU64 = AtomicU64::new(0);
+    NEXT_TOPOLOGY_VERSION.fetch_add(1, Ordering::Relaxed)
+}
+
 fn new_actors_store() -> OrderedSkipMap<PosParam, Actor> {
-    OrderedSkipMap::new(|| Actor::new(Vec::new()))
+    OrderedSkipMap::new()
+}
+/// Actor stores are saved as their (position, actor) pairs in order, since the store's
+/// comparator can't be
+mod actors_serde {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::{new_actors_store, Actor, OrderedSkipMap, PosParam};
+
+    pub fn serialize<S: Serializer>(
+        actors: &OrderedSkipMap<PosParam, Actor>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(actors.enumerate())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<OrderedSkipMap<PosParam, Actor>, D::Error> {
+        let mut actors = new_actors_store();
+        for (pos_param, actor) in Vec::<(PosParam, Actor)>::deserialize(deserializer)? {
+            actors.insert(pos_param, actor);
+        }
+        Ok(actors)
+    }
 }
 impl JunctionLane {
     pub fn new() -> Self {
         Self { actors: new_actors_store() }
     }
 }
+impl Default for JunctionLane {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Segment {
     pub fn new() -> Self {
         Self {
+            shape: Vec::new(),
+            class: RoadClass::Arterial,
+            speed_limit: None,
             forward_lanes: SeqIndexedStore::new(),
             backward_lanes: SeqIndexedStore::new(),
             forward_actors: new_actors_store(),
             backward_actors: new_actors_store(),
         }
     }
-    pub fn add_actor(
-        &mut self,
-        pos_param: PosParam,
-        direction: Direction,
-        agenda: Vec<actor::Agendum>,
-    ) {
-        let actor = Actor::new(agenda);
+    /// Use `Network::add_actor`, which issues IDs
+    fn add_actor(&mut self, pos_param: PosParam, direction: Direction, actor: Actor) {
         match direction {
             Forward => &mut self.forward_actors,
             Backward => &mut self.backward_actors,
         }
-        .insert(pos_param, actor)
+        .insert(pos_param, actor);
+    }
+    pub fn set_shape(&mut self, shape: Vec<Pos>) {
+        self.shape = shape;
+    }
+    pub fn get_shape(&self) -> &[Pos] {
+        &self.shape
+    }
+    pub fn set_class(&mut self, class: RoadClass) {
+        self.class = class;
+    }
+    pub fn get_class(&self) -> RoadClass {
+        self.class
+    }
+    /// `None` goes back to the limit for the segment's class
+    pub fn set_speed_limit(&mut self, speed_limit: Option<f64>) {
+        self.speed_limit = speed_limit;
     }
     pub fn add_lane(&mut self, direction: Direction) {
         let lanes = match direction {
@@ -260,6 +1042,11 @@ impl Segment {
         }
     }
 }
+impl Default for Segment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl SegmentLane {
     pub fn new(direction: Direction) -> Self {
         Self { direction, actors: new_actors_store() }
@@ -279,6 +1066,9 @@ impl CloneEmpty for JunctionLane {
 impl CloneEmpty for Segment {
     fn clone_empty(&self) -> Self {
         Self {
+            shape: self.shape.clone(),
+            class: self.class,
+            speed_limit: self.speed_limit,
             forward_lanes: self.forward_lanes.clone_empty(),
             backward_lanes: self.backward_lanes.clone_empty(),
             forward_actors: new_actors_store(),
@@ -290,6 +1080,8 @@ impl CloneEmpty for Junction {
     fn clone_empty(&self) -> Self {
         Self {
             pos: self.pos,
+            radius: self.radius,
+            priority_inputs: self.priority_inputs.clone(),
             lanes: self.lanes.clone_empty(),
             lane_inputs: self.lane_inputs.clone(),
             lane_inputs_inverse: self.lane_inputs_inverse.clone(),
@@ -304,6 +1096,10 @@ impl CloneEmpty for Network {
             segments: self.segments.clone_empty(),
             junction_segments: self.junction_segments.clone(),
             segment_junctions: self.segment_junctions.clone(),
+            config: self.config.clone(),
+            next_actor_id: self.next_actor_id,
+            geometry_cache: self.geometry_cache.clone(),
+            topology_version: self.topology_version,
         }
     }
 }
@@ -343,20 +1139,44 @@ impl<'a> JunctionLaneContext<'a> {
     ) -> Self {
         assert!(match junction.junction.lanes.get(&id) {
             None => false,
-            Some(context_lane) => lane as *const _ == context_lane as *const _,
+            Some(context_lane) => std::ptr::eq(lane, context_lane),
         });
         Self { junction_ctx: junction, id, lane }
     }
+    pub fn get_segment_lanes(
+        &self,
+    ) -> Result<(QualifiedSegmentLaneRank, QualifiedSegmentLaneRank), RoutieError> {
+        self.junction_ctx.junction.get_segment_lanes_for_junction_lane(self.id)
+    }
+    /// The lower of the limits on the segments it joins
+    pub fn get_speed_limit(&self) -> f64 {
+        let network = self.junction_ctx.network;
+        self.get_segment_lanes()
+            .into_iter()
+            .flat_map(|(input, output)| [input.0, output.0])
+            .filter_map(|id| {
+                let segment = network.get_segment(id).ok()?;
+                Some(SegmentContext::new(network, id, segment).get_speed_limit())
+            })
+            .fold(f64::INFINITY, f64::min)
+    }
 }
 impl<'a> SegmentContext<'a> {
     pub fn new(network: &'a Network, id: SegmentId, segment: &'a Segment) -> Self {
         Self { network, id, segment }
     }
-    pub fn get_junctions(&self) -> (JunctionContext, JunctionContext) {
-        let (begin_id, end_id) = self.network.get_segment_junctions(self.id).unwrap();
-        let id_to_junc_ctx =
-            |id| JunctionContext::new(self.network, id, self.network.junctions.get(&id).unwrap());
-        (id_to_junc_ctx(begin_id), id_to_junc_ctx(end_id))
+    pub fn get_junctions(&self) -> Result<(JunctionContext<'_>, JunctionContext<'_>), RoutieError> {
+        let (begin_id, end_id) = self.network.get_segment_junctions(self.id)?;
+        let id_to_junc_ctx = |id| -> Result<JunctionContext, RoutieError> {
+            Ok(JunctionContext::new(self.network, id, self.network.get_junction(id)?))
+        };
+        Ok((id_to_junc_ctx(begin_id)?, id_to_junc_ctx(end_id)?))
+    }
+    pub fn get_speed_limit(&self) -> f64 {
+        let segment = self.segment;
+        segment
+            .speed_limit
+            .unwrap_or_else(|| self.network.get_config().get_speed_limit(segment.class))
     }
 }
 impl<'a> SegmentLaneContext<'a> {
@@ -368,8 +1188,248 @@ impl<'a> SegmentLaneContext<'a> {
     ) -> Self {
         assert!(match segment_ctx.segment.get_lanes(direction).get(&rank) {
             None => false,
-            Some(context_lane) => lane as *const _ == context_lane as *const _,
+            Some(context_lane) => std::ptr::eq(lane, context_lane),
         });
         Self { segment_ctx, direction, rank, lane }
     }
+    pub fn get_speed_limit(&self) -> f64 {
+        self.segment_ctx.get_speed_limit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use nalgebra::Point2;
+
+    use super::*;
+    use crate::{
+        actor::Agendum,
+        results::{StepEvents, TripRecord},
+        simulate::Simulation,
+    };
+
+    /// A junction with four two-way arms, west, east, north and south, each linked at its
+    /// end away from the centre
+    fn four_arms() -> (Network, JunctionId, Vec<SegmentId>) {
+        let mut network = Network::new();
+        let center = network.add_junction(Point2::new(0.5, 0.5));
+        let mut arms = Vec::new();
+        for (x, y) in [(0.1, 0.5), (0.9, 0.5), (0.5, 0.1), (0.5, 0.9)] {
+            let end = network.add_junction(Point2::new(x, y));
+            let (arm_id, arm) = network.add_segment(end, center).unwrap();
+            arm.add_lane(Forward);
+            arm.add_lane(Backward);
+            arms.push(arm_id);
+        }
+        (network, center, arms)
+    }
+
+    fn count_junction_lanes(network: &Network) -> usize {
+        network.junctions.enumerate().map(|(_, junction)| junction.lanes.len()).sum()
+    }
+
+    /// Every junction lane is in all three lane maps, and nothing else is
+    fn assert_lane_maps_consistent(network: &Network) {
+        for (junction_id, junction) in network.junctions.enumerate() {
+            let lane_count = junction.lanes.len();
+            assert_eq!(junction.lane_inputs_inverse.len(), lane_count);
+            assert_eq!(junction.lane_outputs.len(), lane_count);
+            let input_lane_count: usize = junction.lane_inputs.values().map(|ids| ids.len()).sum();
+            assert_eq!(input_lane_count, lane_count);
+            for (input, lane_ids) in &junction.lane_inputs {
+                for lane_id in lane_ids {
+                    assert!(junction.lanes.get(lane_id).is_some());
+                    assert_eq!(junction.lane_inputs_inverse[lane_id], *input);
+                }
+            }
+            for (lane_id, _) in junction.enumerate_lanes() {
+                let (input, output) =
+                    junction.get_segment_lanes_for_junction_lane(lane_id).unwrap();
+                for segment_lane @ (segment_id, _, _) in [input, output] {
+                    assert!(network.get_segment_lane(segment_lane).is_ok());
+                    assert!(network.get_junction_segments(junction_id).any(|id| id == segment_id));
+                }
+            }
+        }
+    }
+
+    /// `network` with its off-road actors moved onto their start lanes, routes planned
+    fn depart(network: &Network) -> Network {
+        let mut insertions = ActorInsertions::new();
+        let mut events = StepEvents::default();
+        for (segment_id, segment) in network.segments.enumerate() {
+            let segment_ctx = &SegmentContext::new(network, segment_id, segment);
+            for (segment_side, actors) in
+                [(Forward, &segment.forward_actors), (Backward, &segment.backward_actors)]
+            {
+                for (pos_param, actor) in actors.enumerate() {
+                    let actor_ctx = actor::ActorContext::OffRoad {
+                        pos_param: *pos_param,
+                        segment_ctx,
+                        segment_side,
+                        actor,
+                    };
+                    actor_ctx.advance(&mut insertions, 0.0, &mut events).unwrap();
+                }
+            }
+        }
+        let mut network_next = network.clone_empty();
+        network_next.insert_actors(insertions).unwrap();
+        network_next
+    }
+
+    fn travel_to(segment_id: SegmentId) -> Vec<Agendum> {
+        vec![Agendum::TravelTo { segment_id, segment_side: Backward, pos_param: 0.5 }]
+    }
+
+    #[test]
+    fn remove_segment_keeps_lane_maps_consistent() {
+        let (mut network, center, arms) = four_arms();
+        network.connect_junctions(UTurnPolicy::Never);
+        network.remove_segment(arms[0]).unwrap();
+
+        assert!(network.is_connected());
+        assert_eq!(network.get_segment(arms[0]).unwrap_err(), RoutieError::StaleId);
+        assert_eq!(network.get_junction_segments(center).count(), 3);
+        assert_eq!(network.get_junction(center).unwrap().lanes.len(), 6);
+        assert_lane_maps_consistent(&network);
+    }
+
+    #[test]
+    fn remove_lane_keeps_lane_maps_consistent() {
+        let (mut network, center, arms) = four_arms();
+        network.connect_junctions(UTurnPolicy::Never);
+        let lane = (arms[0], Forward, SegmentLaneRank::from(0));
+        network.remove_lane(lane).unwrap();
+
+        assert_eq!(network.get_segment_lane(lane).unwrap_err(), RoutieError::StaleId);
+        // the other arms' lanes into the centre are untouched
+        assert_eq!(network.get_junction(center).unwrap().lanes.len(), 9);
+        assert_lane_maps_consistent(&network);
+    }
+
+    #[test]
+    fn remove_junction_removes_linked_segments() {
+        let (mut network, center, arms) = four_arms();
+        network.connect_junctions(UTurnPolicy::Never);
+        let removed = network.remove_junction(center).unwrap();
+
+        assert_eq!(removed, arms);
+        assert_eq!(network.segments.len(), 0);
+        assert_eq!(network.junctions.len(), 4);
+        assert_eq!(network.get_junction(center).unwrap_err(), RoutieError::StaleId);
+        assert!(network.is_connected());
+        assert_lane_maps_consistent(&network);
+    }
+
+    #[test]
+    fn removal_fails_while_in_use() {
+        let (mut network, center, arms) = four_arms();
+        network.connect_junctions(UTurnPolicy::Never);
+        let actor_id = network
+            .add_actor(arms[0], 0.5, Forward, VehicleParams::default(), travel_to(arms[1]))
+            .unwrap();
+        // off-road on it, and headed for it
+        assert_eq!(network.remove_segment(arms[0]), Err(RoutieError::InUse(actor_id)));
+        assert_eq!(network.remove_segment(arms[1]), Err(RoutieError::InUse(actor_id)));
+
+        let mut network = depart(&network);
+        let destination_lane = (arms[1], Backward, SegmentLaneRank::from(0));
+        // on it, and routed through it
+        assert_eq!(network.remove_junction(center), Err(RoutieError::InUse(actor_id)));
+        assert_eq!(network.remove_lane(destination_lane), Err(RoutieError::InUse(actor_id)));
+        // nothing was removed
+        assert_eq!(network.get_junction(center).unwrap().lanes.len(), 12);
+        assert_lane_maps_consistent(&network);
+
+        network.remove_segment(arms[2]).unwrap();
+        assert_lane_maps_consistent(&network);
+    }
+
+    #[test]
+    fn reconnecting_keeps_junction_lanes() {
+        let (mut network, _, _) = four_arms();
+        network.connect_junctions(UTurnPolicy::Never);
+        // each arm into each other arm
+        assert_eq!(count_junction_lanes(&network), 12);
+        network.connect_junctions(UTurnPolicy::Never);
+        assert_eq!(count_junction_lanes(&network), 12);
+    }
+
+    #[test]
+    fn reusing_spare_keeps_edits() {
+        let (mut network, center, arms) = four_arms();
+        network.connect_junctions(UTurnPolicy::Never);
+        let reused = network.clone_empty_reusing(network.clone_empty());
+        assert_eq!(reused.get_topology_version(), network.get_topology_version());
+
+        let spare = network.clone_empty();
+        network.get_segment_mut(arms[0]).unwrap().set_speed_limit(Some(0.01));
+        network.set_priority_input(center, arms[1]).unwrap();
+        let next = network.clone_empty_reusing(spare);
+        assert_eq!(next.get_segment(arms[0]).unwrap().speed_limit, Some(0.01));
+        assert!(next.get_junction(center).unwrap().priority_inputs.contains(&arms[1]));
+    }
+
+    #[test]
+    fn editing_through_mut_accessors_drops_geometry() {
+        let (mut network, center, arms) = four_arms();
+        network.connect_junctions(UTurnPolicy::Never);
+        let version = network.get_topology_version();
+
+        network.get_junction_mut(center).unwrap().pos = Point2::new(0.6, 0.6);
+        assert!(!network.is_connected());
+        assert_ne!(network.get_topology_version(), version);
+        let junction = network.get_junction(center).unwrap();
+        let footprint = JunctionContext::new(&network, center, junction).get_footprint();
+        assert!(footprint.iter().all(|corner| (corner - Point2::new(0.6, 0.6)).norm() < 0.1));
+
+        network.connect_junctions(UTurnPolicy::Never);
+        network.get_segment_mut(arms[0]).unwrap().set_shape(vec![Point2::new(0.3, 0.7)]);
+        assert!(!network.is_connected());
+    }
+
+    #[test]
+    fn roundabout_on_connected_junction_routes_through_ring() {
+        let (mut network, center, arms) = four_arms();
+        network.connect_junctions(UTurnPolicy::Never);
+        let ring = network.expand_into_roundabout(center, 0.1, 1).unwrap();
+        network.connect_junctions(UTurnPolicy::Never);
+
+        // at each ring junction: arm onto the ring, around the ring, and ring onto arm
+        assert_eq!(count_junction_lanes(&network), 3 * ring.len());
+        for (_, junction) in network.junctions.enumerate() {
+            for (lane_id, _) in junction.enumerate_lanes() {
+                let ((input_id, _, _), (output_id, _, _)) =
+                    junction.get_segment_lanes_for_junction_lane(lane_id).unwrap();
+                assert!(!(arms.contains(&input_id) && arms.contains(&output_id)));
+            }
+        }
+
+        network
+            .add_actor(
+                arms[0],
+                0.5,
+                Forward,
+                VehicleParams::default(),
+                vec![Agendum::TravelTo {
+                    segment_id: arms[1],
+                    segment_side: Backward,
+                    pos_param: 0.5,
+                }],
+            )
+            .unwrap();
+        let mut sim = Simulation::new(network, SimConfig::default());
+        let trips = Rc::new(RefCell::new(Vec::new()));
+        let trips_sink = trips.clone();
+        sim.add_collector(move |trip: &TripRecord| trips_sink.borrow_mut().push(trip.clone()));
+        sim.run_until(600.0).unwrap();
+
+        let trips = trips.borrow();
+        assert_eq!(trips.len(), 1);
+        let route = &trips[0].route;
+        assert!(route.iter().any(|(segment_id, _, _)| !arms.contains(segment_id)));
+    }
 }
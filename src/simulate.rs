@@ -1,55 +1,649 @@
-use crate::{actor, road, util::CloneEmpty};
-
-pub fn advance(network_past: road::Network) -> road::Network {
-    let mut network_future = network_past.clone_empty();
-    for (id, segment) in network_past.segments.enumerate() {
-        let segment_ctx = &road::SegmentContext::new(&network_past, id, segment);
-        for (pos_param, actor) in segment.backward_actors.enumerate() {
-            let actor_ctx = actor::ActorContext::OffRoad {
-                pos_param: *pos_param,
-                segment_ctx,
-                segment_side: road::Direction::Backward,
-                actor,
-            };
-            actor_ctx.advance(&mut network_future);
-        }
-        for (pos_param, actor) in segment.forward_actors.enumerate() {
-            let actor_ctx = actor::ActorContext::OffRoad {
-                pos_param: *pos_param,
-                segment_ctx,
-                segment_side: road::Direction::Forward,
-                actor,
-            };
-            actor_ctx.advance(&mut network_future);
-        }
-        for (rank, lane) in segment.backward_lanes.enumerate() {
-            let lane_ctx =
-                &road::SegmentLaneContext::new(&segment_ctx, road::Direction::Backward, rank, lane);
-            for (pos_param, actor) in lane.actors.enumerate() {
-                let actor_ctx =
-                    actor::ActorContext::OnRoadSegment { pos_param: *pos_param, lane_ctx, actor };
-                actor_ctx.advance(&mut network_future);
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actor,
+    config::SimConfig,
+    demand::Demand,
+    detectors::{DetectorId, Detectors},
+    error::{CheckpointError, RoutieError},
+    metrics::Metrics,
+    random::{Rngs, SimRng, Stream},
+    results::{ResultsCollector, StepEvents},
+    road,
+    util::CloneEmpty,
+};
+
+/// What an observer wants done once it has seen a step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    /// The step still completes, then the simulation is paused, see `Simulation::pause`
+    Pause,
+}
+
+/// Something that looks at the simulation at each step, e.g. to render or record it
+pub trait Observer {
+    fn observe(&mut self, sim: &Simulation) -> Control;
+}
+
+impl<F: FnMut(&Simulation) -> Control> Observer for F {
+    fn observe(&mut self, sim: &Simulation) -> Control {
+        self(sim)
+    }
+}
+
+/// Bumped whenever what's saved changes shape
+const CHECKPOINT_VERSION: u32 = 3;
+
+pub struct Simulation {
+    network: road::Network,
+    /// The network last advanced from, kept so the next step can reuse its lanes
+    network_spare: Option<road::Network>,
+    step_count: u64,
+    paused: bool,
+    rngs: Rngs,
+    demand: Option<Demand>,
+    observers: Vec<Box<dyn Observer>>,
+    collectors: Vec<Box<dyn ResultsCollector>>,
+    metrics: Option<Metrics>,
+    detectors: Detectors,
+}
+
+/// What's saved of a `Simulation`, after `CHECKPOINT_VERSION`
+#[derive(Serialize)]
+struct CheckpointRef<'a> {
+    network: &'a road::Network,
+    connected: bool,
+    step_count: u64,
+    rngs: &'a Rngs,
+    demand: &'a Option<Demand>,
+    metrics: &'a Option<Metrics>,
+    detectors: &'a Detectors,
+}
+
+/// As `CheckpointRef`, field for field
+#[derive(Deserialize)]
+struct Checkpoint {
+    network: road::Network,
+    connected: bool,
+    step_count: u64,
+    rngs: Rngs,
+    demand: Option<Demand>,
+    metrics: Option<Metrics>,
+    detectors: Detectors,
+}
+
+impl Simulation {
+    /// Replaces the network's config with `config`
+    pub fn new(mut network: road::Network, config: SimConfig) -> Self {
+        let rngs = Rngs::new(config.seed);
+        let detectors = Detectors::new(config.detector_interval);
+        network.set_config(config);
+        Self {
+            network,
+            network_spare: None,
+            rngs,
+            step_count: 0,
+            paused: false,
+            demand: None,
+            observers: Vec::new(),
+            collectors: Vec::new(),
+            metrics: None,
+            detectors,
+        }
+    }
+
+    pub fn get_network(&self) -> &road::Network {
+        &self.network
+    }
+
+    pub fn get_config(&self) -> &SimConfig {
+        self.network.get_config()
+    }
+
+    /// Actors have streams of their own, see `actor::Actor::get_rng`
+    pub fn get_rng(&mut self, stream: Stream) -> &mut SimRng {
+        self.rngs.get(stream)
+    }
+
+    /// Simulated seconds since the start
+    pub fn get_time(&self) -> f64 {
+        // rather than accumulating, which drifts
+        self.step_count as f64 * self.get_config().time_step
+    }
+
+    pub fn get_step_count(&self) -> u64 {
+        self.step_count
+    }
+
+    /// Replaces any demand set before
+    pub fn set_demand(&mut self, demand: Demand) {
+        self.demand = Some(demand);
+    }
+
+    /// Observers see the network as of the start of each step, before it's advanced
+    pub fn add_observer(&mut self, observer: impl Observer + 'static) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// Collectors get each trip as it finishes
+    pub fn add_collector(&mut self, collector: impl ResultsCollector + 'static) {
+        self.collectors.push(Box::new(collector));
+    }
+
+    /// Starts gathering metrics, aggregated over `interval` seconds, replacing any gathered so far
+    pub fn enable_metrics(&mut self, interval: f64) {
+        self.metrics = Some(Metrics::new(interval));
+    }
+
+    pub fn get_metrics(&self) -> Option<&Metrics> {
+        self.metrics.as_ref()
+    }
+
+    /// Places a detector at `pos_param` along `lane`
+    pub fn add_detector(
+        &mut self,
+        lane: road::QualifiedSegmentLaneRank,
+        pos_param: road::PosParam,
+    ) -> Result<DetectorId, RoutieError> {
+        self.detectors.add(&self.network, lane, pos_param)
+    }
+
+    pub fn get_detectors(&self) -> &Detectors {
+        &self.detectors
+    }
+
+    /// Saves everything needed to carry on from the current step, except observers and
+    /// collectors. The file is replaced in one go, so a crash while saving leaves the
+    /// previous checkpoint intact.
+    pub fn save_checkpoint(&self, path: impl AsRef<Path>) -> Result<(), CheckpointError> {
+        let path = path.as_ref();
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let checkpoint = CheckpointRef {
+            network: &self.network,
+            connected: self.network.is_connected(),
+            step_count: self.step_count,
+            rngs: &self.rngs,
+            demand: &self.demand,
+            metrics: &self.metrics,
+            detectors: &self.detectors,
+        };
+        let mut writer = BufWriter::new(File::create(&tmp_path).map_err(CheckpointError::Io)?);
+        bincode::serialize_into(&mut writer, &CHECKPOINT_VERSION)
+            .map_err(CheckpointError::Encoding)?;
+        bincode::serialize_into(&mut writer, &checkpoint).map_err(CheckpointError::Encoding)?;
+        writer.flush().map_err(CheckpointError::Io)?;
+        fs::rename(&tmp_path, path).map_err(CheckpointError::Io)
+    }
+
+    /// Carries on from a checkpoint, with its config. Steps from here on go exactly as
+    /// they would have in the saved run. Observers and collectors need adding again.
+    pub fn restore(path: impl AsRef<Path>) -> Result<Self, CheckpointError> {
+        let mut reader = BufReader::new(File::open(path).map_err(CheckpointError::Io)?);
+        let version: u32 =
+            bincode::deserialize_from(&mut reader).map_err(CheckpointError::Encoding)?;
+        if version != CHECKPOINT_VERSION {
+            return Err(CheckpointError::UnsupportedVersion(version));
+        }
+        let checkpoint: Checkpoint =
+            bincode::deserialize_from(&mut reader).map_err(CheckpointError::Encoding)?;
+        let mut network = checkpoint.network;
+        // derived from the rest, so not saved
+        if checkpoint.connected {
+            network.rebuild_geometry_cache();
+        }
+        Ok(Self {
+            network,
+            network_spare: None,
+            step_count: checkpoint.step_count,
+            paused: false,
+            rngs: checkpoint.rngs,
+            demand: checkpoint.demand,
+            observers: Vec::new(),
+            collectors: Vec::new(),
+            metrics: checkpoint.metrics,
+            detectors: checkpoint.detectors,
+        })
+    }
+
+    /// Stops `run_until` and `run_for` before their next step. `step` still works. Observers
+    /// can ask for the same from inside a run by returning `Control::Pause`.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Spawns the step's departures, then advances by one time step. Actors that can't be
+    /// advanced are dropped and reported to collectors, rather than failing the step. On
+    /// error the simulation is paused, with the network as it was before advancing.
+    pub fn step(&mut self) -> Result<(), RoutieError> {
+        let time = self.get_time();
+        if let Some(demand) = &mut self.demand {
+            if let Err(e) = demand.spawn(&mut self.network, time, self.rngs.get(Stream::Demand)) {
+                self.paused = true;
+                return Err(e);
             }
         }
-        for (rank, lane) in segment_ctx.segment.forward_lanes.enumerate() {
-            let lane_ctx =
-                &road::SegmentLaneContext::new(&segment_ctx, road::Direction::Forward, rank, lane);
-            for (pos_param, actor) in lane.actors.enumerate() {
-                let actor_ctx =
-                    actor::ActorContext::OnRoadSegment { pos_param: *pos_param, lane_ctx, actor };
-                actor_ctx.advance(&mut network_future);
+
+        let mut observers = std::mem::take(&mut self.observers);
+        let mut pause_requested = false;
+        for observer in &mut observers {
+            pause_requested |= observer.observe(self) == Control::Pause;
+        }
+        self.observers = observers;
+
+        let network_future = match self.network_spare.take() {
+            Some(spare) => self.network.clone_empty_reusing(spare),
+            None => self.network.clone_empty(),
+        };
+        match advance(&self.network, network_future, time) {
+            Ok((network_next, events)) => {
+                for (id, error) in &events.dropped_actors {
+                    log::warn!("{:?} dropped: {}", id, error);
+                }
+                for collector in &mut self.collectors {
+                    for trip in &events.trips {
+                        collector.record_trip(trip);
+                    }
+                    for (id, error) in &events.dropped_actors {
+                        collector.record_dropped_actor(*id, error);
+                    }
+                }
+                if let Some(metrics) = &mut self.metrics {
+                    metrics.record_step(&self.network, time, &events);
+                }
+                self.detectors.record_step(&self.network, time, &events);
+                self.network_spare = Some(std::mem::replace(&mut self.network, network_next));
+                self.step_count += 1;
+                self.paused |= pause_requested;
+                Ok(())
+            }
+            Err(e) => {
+                self.paused = true;
+                Err(e)
             }
         }
     }
-    for (id, junction) in network_past.junctions.enumerate() {
-        let junction_ctx = &road::JunctionContext::new(&network_past, id, junction);
-        for (id, lane) in junction.lanes.enumerate() {
-            let lane_ctx = &road::JunctionLaneContext::new(junction_ctx, id, lane);
-            for (pos_param, actor) in lane.actors.enumerate() {
-                let actor_ctx = &actor::ActorContext::OnRoadJunction { pos_param: *pos_param, lane_ctx, actor };
-                actor_ctx.advance(&mut network_future)
-            }
+
+    /// Steps until simulated time reaches `time`, or until paused
+    pub fn run_until(&mut self, time: f64) -> Result<(), RoutieError> {
+        while !self.paused && self.get_time() < time {
+            self.step()?;
         }
+        Ok(())
+    }
+
+    pub fn run_for(&mut self, duration: f64) -> Result<(), RoutieError> {
+        self.run_until(self.get_time() + duration)
+    }
+}
+
+/// Fills `network_future`, an empty copy of `network_past`, and returns it along with what
+/// happened during the step. Segments and junctions are stepped in
+/// parallel, on rayon's global thread pool. Results don't depend on the number of threads,
+/// since their actors are merged into the next network in order.
+fn advance(
+    network_past: &road::Network,
+    mut network_future: road::Network,
+    time: f64,
+) -> Result<(road::Network, StepEvents), RoutieError> {
+    let segments: Vec<_> = network_past.segments.enumerate().collect();
+    let junctions: Vec<_> = network_past.junctions.enumerate().collect();
+    let segment_results: Vec<_> = segments
+        .par_iter()
+        .map(|(id, segment)| advance_segment(network_past, *id, segment, time))
+        .collect();
+    let junction_results: Vec<_> = junctions
+        .par_iter()
+        .map(|(id, junction)| advance_junction(network_past, *id, junction, time))
+        .collect();
+
+    let mut events = StepEvents::default();
+    for (insertions, part_events) in segment_results.into_iter().chain(junction_results) {
+        network_future.insert_actors(insertions)?;
+        events.append(part_events);
+    }
+    Ok((network_future, events))
+}
+
+/// Advances one actor, or drops it if it can't be, along with whatever it did this step.
+/// An actor only pushes itself to `insertions` once nothing more can go wrong.
+fn advance_actor(
+    actor_ctx: &actor::ActorContext,
+    insertions: &mut road::ActorInsertions,
+    time: f64,
+    events: &mut StepEvents,
+) {
+    let mut actor_events = StepEvents::default();
+    match actor_ctx.advance(insertions, time, &mut actor_events) {
+        Ok(()) => events.append(actor_events),
+        Err(error) => events.dropped_actors.push((actor_ctx.get_actor().get_id(), error)),
+    }
+}
+
+/// Actors on the segment, off-road or in its lanes
+fn advance_segment(
+    network_past: &road::Network,
+    id: road::SegmentId,
+    segment: &road::Segment,
+    time: f64,
+) -> (road::ActorInsertions, StepEvents) {
+    let mut insertions = road::ActorInsertions::new();
+    let mut events = StepEvents::default();
+    let segment_ctx = &road::SegmentContext::new(network_past, id, segment);
+    for (pos_param, actor) in segment.backward_actors.enumerate() {
+        let actor_ctx = actor::ActorContext::OffRoad {
+            pos_param: *pos_param,
+            segment_ctx,
+            segment_side: road::Direction::Backward,
+            actor,
+        };
+        advance_actor(&actor_ctx, &mut insertions, time, &mut events);
+    }
+    for (pos_param, actor) in segment.forward_actors.enumerate() {
+        let actor_ctx = actor::ActorContext::OffRoad {
+            pos_param: *pos_param,
+            segment_ctx,
+            segment_side: road::Direction::Forward,
+            actor,
+        };
+        advance_actor(&actor_ctx, &mut insertions, time, &mut events);
+    }
+    for (rank, lane) in segment.backward_lanes.enumerate() {
+        let lane_ctx =
+            &road::SegmentLaneContext::new(segment_ctx, road::Direction::Backward, rank, lane);
+        for (pos_param, actor) in lane.actors.enumerate() {
+            let actor_ctx =
+                actor::ActorContext::OnRoadSegment { pos_param: *pos_param, lane_ctx, actor };
+            advance_actor(&actor_ctx, &mut insertions, time, &mut events);
+        }
+    }
+    for (rank, lane) in segment_ctx.segment.forward_lanes.enumerate() {
+        let lane_ctx =
+            &road::SegmentLaneContext::new(segment_ctx, road::Direction::Forward, rank, lane);
+        for (pos_param, actor) in lane.actors.enumerate() {
+            let actor_ctx =
+                actor::ActorContext::OnRoadSegment { pos_param: *pos_param, lane_ctx, actor };
+            advance_actor(&actor_ctx, &mut insertions, time, &mut events);
+        }
+    }
+    (insertions, events)
+}
+
+fn advance_junction(
+    network_past: &road::Network,
+    id: road::JunctionId,
+    junction: &road::Junction,
+    time: f64,
+) -> (road::ActorInsertions, StepEvents) {
+    let mut insertions = road::ActorInsertions::new();
+    let mut events = StepEvents::default();
+    let junction_ctx = &road::JunctionContext::new(network_past, id, junction);
+    for (id, lane) in junction.lanes.enumerate() {
+        let lane_ctx = &road::JunctionLaneContext::new(junction_ctx, id, lane);
+        for (pos_param, actor) in lane.actors.enumerate() {
+            let actor_ctx = &actor::ActorContext::OnRoadJunction { pos_param: *pos_param, lane_ctx, actor };
+            advance_actor(actor_ctx, &mut insertions, time, &mut events);
+        }
+    }
+    (insertions, events)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use nalgebra::Point2;
+
+    use super::*;
+    use crate::{
+        actor::{Actor, ActorId, Agendum, RouteStep},
+        demand::{DepartureProfile, Place},
+        replay::Frame,
+        results::TripRecord,
+        road::{
+            Direction::{Backward, Forward},
+            UTurnPolicy,
+        },
+        vehicle::VehicleParams,
+    };
+
+    #[derive(Clone, Default)]
+    struct Recorder {
+        trips: Rc<RefCell<Vec<TripRecord>>>,
+        dropped: Rc<RefCell<Vec<ActorId>>>,
+    }
+
+    impl ResultsCollector for Recorder {
+        fn record_trip(&mut self, trip: &TripRecord) {
+            self.trips.borrow_mut().push(trip.clone());
+        }
+        fn record_dropped_actor(&mut self, id: ActorId, _error: &RoutieError) {
+            self.dropped.borrow_mut().push(id);
+        }
+    }
+
+    /// Adds two junctions at height `y` and a two-way segment between them
+    fn add_shuttle(network: &mut road::Network, y: f64) -> road::SegmentId {
+        let west = network.add_junction(Point2::new(0.2, y));
+        let east = network.add_junction(Point2::new(0.8, y));
+        let (segment_id, segment) = network.add_segment(west, east).unwrap();
+        segment.add_lane(Forward);
+        segment.add_lane(road::Direction::Backward);
+        segment_id
+    }
+
+    fn travel_to(segment_id: road::SegmentId) -> Agendum {
+        Agendum::TravelTo { segment_id, segment_side: Forward, pos_param: 0.9 }
+    }
+
+    /// A triangle of two-way segments, with trips between any two of them
+    fn demand_sim(seed: u64) -> Simulation {
+        let mut network = road::Network::new();
+        let corners = [(0.2, 0.2), (0.8, 0.2), (0.5, 0.8)]
+            .map(|(x, y)| network.add_junction(Point2::new(x, y)));
+        let mut segments = Vec::new();
+        for (idx, junction_id) in corners.iter().enumerate() {
+            let (segment_id, segment) =
+                network.add_segment(*junction_id, corners[(idx + 1) % corners.len()]).unwrap();
+            segment.add_lane(Forward);
+            segment.add_lane(Backward);
+            segments.push(segment_id);
+        }
+        network.connect_junctions(UTurnPolicy::DeadEnds);
+        let mut demand = Demand::new();
+        demand.add_zone("all", segments);
+        let all = || Place::Zone("all".to_string());
+        demand.add_trips(all(), all(), DepartureProfile::Constant(0.2));
+        let mut sim = Simulation::new(network, SimConfig { seed, ..SimConfig::default() });
+        sim.set_demand(demand);
+        sim
+    }
+
+    fn capture(sim: &Simulation) -> Frame {
+        Frame::capture(sim.get_network(), sim.get_step_count(), sim.get_time())
+    }
+
+    #[test]
+    fn observer_pauses_run() {
+        let mut sim = Simulation::new(road::Network::new(), SimConfig::default());
+        sim.add_observer(|sim: &Simulation| {
+            if sim.get_step_count() == 3 {
+                Control::Pause
+            } else {
+                Control::Continue
+            }
+        });
+        sim.run_until(f64::INFINITY).unwrap();
+        assert!(sim.is_paused());
+        // the step the observer saw still completes
+        assert_eq!(sim.get_step_count(), 4);
+
+        sim.resume();
+        sim.run_for(2.5 * sim.get_config().time_step).unwrap();
+        assert!(!sim.is_paused());
+        assert_eq!(sim.get_step_count(), 7);
+    }
+
+    #[test]
+    fn actor_sleeps_before_travelling() {
+        let mut network = road::Network::new();
+        let segment_id = add_shuttle(&mut network, 0.5);
+        network.connect_junctions(UTurnPolicy::DeadEnds);
+        let agenda = vec![travel_to(segment_id), Agendum::SleepFor(5)];
+        network.add_actor(segment_id, 0.1, Forward, VehicleParams::default(), agenda).unwrap();
+        let mut sim = Simulation::new(network, SimConfig::default());
+        let recorder = Recorder::default();
+        sim.add_collector(recorder.clone());
+        sim.run_until(60.0).unwrap();
+
+        let trips = recorder.trips.borrow();
+        assert_eq!(trips.len(), 1);
+        // wakes at the first step at or after 5 s, and sets off on the one after
+        assert_eq!(trips[0].depart_time, 8.0);
+    }
+
+    #[test]
+    fn destination_behind_start_is_reached_by_going_around() {
+        let mut network = road::Network::new();
+        let segment_id = add_shuttle(&mut network, 0.5);
+        network.connect_junctions(UTurnPolicy::DeadEnds);
+        let agenda = vec![Agendum::TravelTo { segment_id, segment_side: Forward, pos_param: 0.3 }];
+        network.add_actor(segment_id, 0.7, Forward, VehicleParams::default(), agenda).unwrap();
+        let mut sim = Simulation::new(network, SimConfig::default());
+        let recorder = Recorder::default();
+        sim.add_collector(recorder.clone());
+        sim.run_until(120.0).unwrap();
+
+        let trips = recorder.trips.borrow();
+        assert_eq!(trips.len(), 1);
+        // out to the east end, back along the other lane, and in again from the west end
+        assert_eq!(trips[0].route.len(), 3);
+        assert!(trips[0].distance > 0.6);
+        assert!(trips[0].arrive_time > trips[0].depart_time + sim.get_config().time_step);
+    }
+
+    #[test]
+    fn unroutable_actor_is_dropped() {
+        let mut network = road::Network::new();
+        let segment_id = add_shuttle(&mut network, 0.5);
+        let unreachable_id = add_shuttle(&mut network, 0.1);
+        network.connect_junctions(UTurnPolicy::DeadEnds);
+        let vehicle = VehicleParams::default();
+        let stuck = network
+            .add_actor(segment_id, 0.1, Forward, vehicle, vec![travel_to(unreachable_id)])
+            .unwrap();
+        network.add_actor(segment_id, 0.2, Forward, vehicle, vec![travel_to(segment_id)]).unwrap();
+        let mut sim = Simulation::new(network, SimConfig::default());
+        let recorder = Recorder::default();
+        sim.add_collector(recorder.clone());
+        sim.run_until(60.0).unwrap();
+
+        assert_eq!(*recorder.dropped.borrow(), vec![stuck]);
+        assert_eq!(recorder.trips.borrow().len(), 1);
+    }
+
+    #[test]
+    fn actor_changes_lane() {
+        let mut network = road::Network::new();
+        let segment_id = add_shuttle(&mut network, 0.5);
+        network.get_segment_mut(segment_id).unwrap().add_lane(Forward);
+        network.connect_junctions(UTurnPolicy::DeadEnds);
+        let ranks: Vec<_> = network
+            .get_segment(segment_id)
+            .unwrap()
+            .get_lanes(Forward)
+            .enumerate()
+            .map(|(rank, _)| rank)
+            .collect();
+        let lanes = [(segment_id, Forward, ranks[0]), (segment_id, Forward, ranks[1])];
+        let mut actor = Actor::new(ActorId(0), 0, VehicleParams::default(), Vec::new());
+        actor.route_push(RouteStep::ArriveAt(0.9));
+        actor.route_push(RouteStep::LaneChange(ranks[1]));
+        let mut insertions = road::ActorInsertions::new();
+        insertions.push_segment_lane(lanes[0], 0.1, actor);
+        network.insert_actors(insertions).unwrap();
+        let mut sim = Simulation::new(network, SimConfig::default());
+
+        sim.step().unwrap();
+        let count_on = |sim: &Simulation, lane| {
+            sim.get_network().get_segment_lane(lane).unwrap().actors.enumerate().count()
+        };
+        assert_eq!(count_on(&sim, lanes[0]), 0);
+        assert_eq!(count_on(&sim, lanes[1]), 1);
+    }
+
+    #[test]
+    fn restored_run_carries_on_as_before() {
+        let path = std::env::temp_dir()
+            .join(format!("routie-test-{}-restored.checkpoint", std::process::id()));
+        let mut whole = demand_sim(7);
+        let whole_recorder = Recorder::default();
+        whole.add_collector(whole_recorder.clone());
+        whole.run_until(120.0).unwrap();
+
+        let mut first = demand_sim(7);
+        let recorder = Recorder::default();
+        first.add_collector(recorder.clone());
+        first.run_until(60.0).unwrap();
+        first.save_checkpoint(&path).unwrap();
+        let mut second = Simulation::restore(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        second.add_collector(recorder.clone());
+        second.run_until(120.0).unwrap();
+
+        assert!(!whole_recorder.trips.borrow().is_empty());
+        assert_eq!(*recorder.trips.borrow(), *whole_recorder.trips.borrow());
+        assert_eq!(capture(&second), capture(&whole));
+    }
+
+    #[test]
+    fn checkpoint_from_other_version_is_rejected() {
+        let path = std::env::temp_dir()
+            .join(format!("routie-test-{}-version.checkpoint", std::process::id()));
+        demand_sim(7).save_checkpoint(&path).unwrap();
+        // overwrite the version at the start, keeping the rest
+        let mut bytes = fs::read(&path).unwrap();
+        let version = bincode::serialize(&(CHECKPOINT_VERSION + 1)).unwrap();
+        bytes[..version.len()].copy_from_slice(&version);
+        fs::write(&path, bytes).unwrap();
+        let restored = Simulation::restore(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            restored,
+            Err(CheckpointError::UnsupportedVersion(version)) if version == CHECKPOINT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn same_seed_gives_same_trips() {
+        let run = |seed| {
+            // built from scratch each time, so hash set order differs between runs too
+            let mut sim = demand_sim(seed);
+            let recorder = Recorder::default();
+            sim.add_collector(recorder.clone());
+            sim.run_until(120.0).unwrap();
+            recorder.trips.take()
+        };
+        let trips = run(11);
+        assert!(!trips.is_empty());
+        assert_eq!(run(11), trips);
+        assert_ne!(run(12), trips);
     }
-    network_future
 }
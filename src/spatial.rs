@@ -1,178 +1,701 @@
-use std::f64::consts::{FRAC_PI_2, PI};
+use std::{borrow::Cow, collections::HashMap, f64::consts::FRAC_PI_2};
 
-use lyon_geom::QuadraticBezierSegment;
+use lyon_geom::{CubicBezierSegment, QuadraticBezierSegment};
 // TODO: use lyon_geom stuff instead
 use nalgebra::{Point2, Rotation2, Vector2};
 
 use crate::{
     actor,
-    constants::{ROAD_JUNCTION_RADIUS, ROAD_LANE_WIDTH, ROAD_SEGMENT_WIGGLE_ROOM_PCT},
+    error::RoutieError,
     road::{
-        self,
+        self, Direction,
         Direction::{Backward, Forward},
-        QualifiedSegmentLaneRank, SegmentContext, SegmentLaneContext,
+        JunctionId, JunctionLaneId, QualifiedSegmentLaneRank, SegmentContext, SegmentId,
+        SegmentLaneContext,
     },
 };
 
 pub type Pos = Point2<f64>;
 pub type Vector = Vector2<f64>;
 
+/// Static geometry of a connected network, so it isn't recomputed every step and frame.
+/// Built by `Network::connect_junctions`, dropped by any edit to the network.
+#[derive(Debug, Default)]
+pub struct GeometryCache {
+    junctions: HashMap<JunctionId, JunctionGeometry>,
+    segment_lanes: HashMap<QualifiedSegmentLaneRank, SegmentLaneGeometry>,
+    junction_lanes: HashMap<(JunctionId, JunctionLaneId), JunctionLaneGeometry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct JunctionGeometry {
+    pub footprint: Vec<Pos>,
+    /// setback of each linked segment end from the junction center,
+    /// keyed by the direction of travel that arrives at the junction
+    pub trims: HashMap<(SegmentId, Direction), f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SegmentLaneGeometry {
+    pub pos: (Pos, Pos),
+    pub length: f64,
+    pub polyline: Polyline,
+}
+
+#[derive(Debug, Clone)]
+pub struct JunctionLaneGeometry {
+    pub pos: (Pos, Pos),
+    pub curve: CubicBezierSegment<f64>,
+    pub length: f64,
+    /// (curve param, arc length up to it), ascending in both
+    pub arc_lengths: Vec<(f64, f64)>,
+}
+
+impl GeometryCache {
+    pub fn build(network: &road::Network) -> Self {
+        let tolerance = network.get_config().curve_flattening_tolerance;
+        let mut cache = Self::default();
+        for (junction_id, junction) in network.junctions.enumerate() {
+            let junction_ctx = road::JunctionContext::new(network, junction_id, junction);
+            cache.junctions.insert(
+                junction_id,
+                JunctionGeometry {
+                    footprint: junction_ctx.compute_footprint(),
+                    trims: junction_ctx.compute_trims(),
+                },
+            );
+        }
+        // left out where there's no telling, e.g. on a segment not linked to junctions, which
+        // `Network::validate` reports, so that looking them up gives the error instead
+        for (segment_id, segment) in network.segments.enumerate() {
+            let segment_ctx = &SegmentContext::new(network, segment_id, segment);
+            for direction in [Forward, Backward] {
+                for (rank, lane) in segment.get_lanes(direction).enumerate() {
+                    let lane_ctx = SegmentLaneContext::new(segment_ctx, direction, rank, lane);
+                    let polyline = match lane_ctx.compute_polyline() {
+                        Ok(polyline) => polyline,
+                        Err(_) => continue,
+                    };
+                    let points = polyline.get_points();
+                    let pos = (points[0], points[points.len() - 1]);
+                    cache.segment_lanes.insert(
+                        (segment_id, direction, rank),
+                        SegmentLaneGeometry { pos, length: polyline.get_length(), polyline },
+                    );
+                }
+            }
+        }
+        for (junction_id, junction) in network.junctions.enumerate() {
+            let junction_ctx = &road::JunctionContext::new(network, junction_id, junction);
+            for (lane_id, lane) in junction.enumerate_lanes() {
+                let lane_ctx = road::JunctionLaneContext::new(junction_ctx, lane_id, lane);
+                let (pos, curve) = match (lane_ctx.compute_pos(), lane_ctx.compute_curve()) {
+                    (Ok(pos), Ok(curve)) => (pos, curve),
+                    _ => continue,
+                };
+                let arc_lengths = build_arc_length_table(&curve, tolerance);
+                cache.junction_lanes.insert(
+                    (junction_id, lane_id),
+                    JunctionLaneGeometry {
+                        pos,
+                        curve,
+                        length: arc_lengths.last().unwrap().1,
+                        arc_lengths,
+                    },
+                );
+            }
+        }
+        cache
+    }
+
+    pub fn get_junction(&self, junction_id: JunctionId) -> Option<&JunctionGeometry> {
+        self.junctions.get(&junction_id)
+    }
+
+    pub fn get_segment_lane(&self, lane: QualifiedSegmentLaneRank) -> Option<&SegmentLaneGeometry> {
+        self.segment_lanes.get(&lane)
+    }
+
+    pub fn get_junction_lane(
+        &self,
+        junction_id: JunctionId,
+        lane_id: JunctionLaneId,
+    ) -> Option<&JunctionLaneGeometry> {
+        self.junction_lanes.get(&(junction_id, lane_id))
+    }
+}
+
+/// Inverse of the arc length table: curve param at `arc_length` along the curve
+fn lookup_curve_param(arc_lengths: &[(f64, f64)], arc_length: f64) -> f64 {
+    let idx = arc_lengths.partition_point(|(_, length)| *length < arc_length);
+    if idx == 0 {
+        return 0.0;
+    }
+    if idx == arc_lengths.len() {
+        return 1.0;
+    }
+    let (t_prev, length_prev) = arc_lengths[idx - 1];
+    let (t_next, length_next) = arc_lengths[idx];
+    t_prev + (t_next - t_prev) * (arc_length - length_prev) / (length_next - length_prev)
+}
+
+fn build_arc_length_table(curve: &CubicBezierSegment<f64>, tolerance: f64) -> Vec<(f64, f64)> {
+    let mut table = vec![(0.0, 0.0)];
+    let mut length = 0.0;
+    curve.for_each_flattened_with_t(tolerance, &mut |line, t_range| {
+        length += line.length();
+        table.push((t_range.end, length));
+    });
+    table
+}
+
+/// Counterclockwise, starting from the leftmost point, without collinear points
+fn convex_hull(mut points: Vec<Pos>) -> Vec<Pos> {
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    // Andrew's monotone chain, keeping only left turns
+    let half_hull = |points: &mut dyn Iterator<Item = &Pos>| {
+        let mut chain: Vec<Pos> = Vec::new();
+        for point in points {
+            while let [.., a, b] = chain[..] {
+                if (b - a).perp(&(point - a)) > 0.0 {
+                    break;
+                }
+                chain.pop();
+            }
+            chain.push(*point);
+        }
+        // the last point starts the other half
+        chain.pop();
+        chain
+    };
+    let mut hull = half_hull(&mut points.iter());
+    hull.extend(half_hull(&mut points.iter().rev()));
+    hull
+}
+
+/// Separating axis test, for convex polygons such as junction footprints
+pub fn polygons_overlap(a: &[Pos], b: &[Pos]) -> bool {
+    let rot = Rotation2::new(FRAC_PI_2);
+    let edge_normals = |polygon: &[Pos]| -> Vec<Vector> {
+        (0..polygon.len())
+            .map(|idx| rot * (polygon[(idx + 1) % polygon.len()] - polygon[idx]))
+            .collect()
+    };
+    let project = |polygon: &[Pos], axis: &Vector| {
+        polygon
+            .iter()
+            .map(|p| p.coords.dot(axis))
+            .fold((f64::MAX, f64::MIN), |(min, max), x| (min.min(x), max.max(x)))
+    };
+    edge_normals(a).iter().chain(edge_normals(b).iter()).all(|axis| {
+        let (a_min, a_max) = project(a, axis);
+        let (b_min, b_max) = project(b, axis);
+        // touching doesn't count
+        let tolerance = 1e-9 * axis.norm();
+        a_min < b_max - tolerance && b_min < a_max - tolerance
+    })
+}
+
 pub trait PointLike {
-    fn get_pos(&self) -> Pos;
+    fn get_pos(&self) -> Result<Pos, RoutieError>;
 }
 
 impl<'a> PointLike for road::JunctionContext<'a> {
-    fn get_pos(&self) -> Pos {
-        self.junction.pos
+    fn get_pos(&self) -> Result<Pos, RoutieError> {
+        Ok(self.junction.pos)
+    }
+}
+
+struct SegmentEnd {
+    key: (SegmentId, Direction),
+    /// pointing away from the junction
+    v_norm: Vector,
+    half_width: f64,
+    leg_length: f64,
+}
+
+impl<'a> road::JunctionContext<'a> {
+    fn get_cached(&self) -> Option<&'a JunctionGeometry> {
+        self.network.get_geometry_cache()?.get_junction(self.id)
+    }
+
+    pub fn get_footprint(&self) -> Vec<Pos> {
+        match self.get_cached() {
+            Some(geometry) => geometry.footprint.clone(),
+            None => self.compute_footprint(),
+        }
+    }
+
+    /// How far the end of a linked segment is set back from the junction center
+    pub fn get_trim(&self, segment_id: SegmentId, direction: Direction) -> f64 {
+        let trim = match self.get_cached() {
+            Some(geometry) => geometry.trims.get(&(segment_id, direction)).copied(),
+            None => self.compute_trims().get(&(segment_id, direction)).copied(),
+        };
+        trim.unwrap_or(self.network.get_config().junction_radius)
+    }
+
+    fn get_segment_ends(&self) -> Vec<SegmentEnd> {
+        let mut segment_ends = Vec::new();
+        for segment_id in self.network.get_junction_segments(self.id) {
+            let segment = match self.network.get_segment(segment_id) {
+                Ok(segment) => segment,
+                Err(_) => continue,
+            };
+            let segment_ctx = SegmentContext::new(self.network, segment_id, segment);
+            // left out if it can't be drawn, e.g. its other junction was removed directly
+            let (begin_junction_id, end_junction_id) =
+                match self.network.get_segment_junctions(segment_id) {
+                    Ok(junction_ids) => junction_ids,
+                    Err(_) => continue,
+                };
+            let points = match segment_ctx.get_untrimmed_points() {
+                Ok(points) => points,
+                Err(_) => continue,
+            };
+            let last_idx = points.len() - 1;
+            let half_width = 0.5 * segment_ctx.get_width();
+            let mut push_end = |direction, v: Vector| {
+                // a leg with no length, as on a self-loop or between coincident junctions,
+                // has no direction to trim along
+                if v.norm() <= f64::EPSILON {
+                    return;
+                }
+                segment_ends.push(SegmentEnd {
+                    key: (segment_id, direction),
+                    v_norm: v.normalize(),
+                    half_width,
+                    leg_length: v.norm(),
+                })
+            };
+            if begin_junction_id == self.id {
+                push_end(Backward, points[1] - points[0]);
+            }
+            if end_junction_id == self.id {
+                push_end(Forward, points[last_idx - 1] - points[last_idx]);
+            }
+        }
+        segment_ends
+    }
+
+    fn compute_trims(&self) -> HashMap<(SegmentId, Direction), f64> {
+        let segment_ends = self.get_segment_ends();
+        let get_trim = |segment_end: &SegmentEnd| {
+            if let Some(radius) = self.junction.get_radius() {
+                return radius;
+            }
+            // back off until this segment's edges clear those of every other segment
+            let mut trim = segment_end.half_width;
+            for other in segment_ends.iter().filter(|other| other.key != segment_end.key) {
+                let cos = segment_end.v_norm.dot(&other.v_norm);
+                let sin = segment_end.v_norm.perp(&other.v_norm).abs();
+                if sin > f64::EPSILON {
+                    trim = trim.max((other.half_width + segment_end.half_width * cos) / sin);
+                }
+            }
+            trim.min(0.5 * segment_end.leg_length)
+        };
+        segment_ends.iter().map(|segment_end| (segment_end.key, get_trim(segment_end))).collect()
+    }
+
+    fn compute_footprint(&self) -> Vec<Pos> {
+        let pos = self.junction.pos;
+        let segment_ends = self.get_segment_ends();
+        if segment_ends.is_empty() {
+            let radius =
+                self.junction.get_radius().unwrap_or(self.network.get_config().junction_radius);
+            return [(1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0), (1.0, -1.0)]
+                .iter()
+                .map(|(x, y)| pos + radius * Vector::new(*x, *y))
+                .collect();
+        }
+        let trims = self.compute_trims();
+        let rot = Rotation2::new(FRAC_PI_2);
+        let mut corners = Vec::new();
+        for segment_end in &segment_ends {
+            let trim = trims[&segment_end.key];
+            let v_ortho = segment_end.half_width * (rot * segment_end.v_norm);
+            let edge_midpoint = pos + trim * segment_end.v_norm;
+            corners.push(edge_midpoint + v_ortho);
+            corners.push(edge_midpoint - v_ortho);
+            if segment_ends.len() == 1 {
+                // dead end, close it off behind the center
+                let back_midpoint = pos - segment_end.half_width * segment_end.v_norm;
+                corners.push(back_midpoint + v_ortho);
+                corners.push(back_midpoint - v_ortho);
+            }
+        }
+        convex_hull(corners)
     }
 }
 
 impl<'a> PointLike for actor::ActorContext<'a> {
-    fn get_pos(&self) -> Pos {
-        match self {
-            actor::ActorContext::OffRoad { pos_param, segment_ctx, segment_side, actor } => {
-                let (segment_begin_pos, _) = segment_ctx.get_pos();
+    fn get_pos(&self) -> Result<Pos, RoutieError> {
+        Ok(match self {
+            actor::ActorContext::OffRoad { pos_param, segment_ctx, segment_side, .. } => {
+                let polyline = segment_ctx.get_polyline()?;
                 let (offset_direction, scalar) = match segment_side {
                     road::Direction::Forward => (1.0, *pos_param),
                     road::Direction::Backward => (-1.0, 1.0 - *pos_param),
                 };
-                let offset = offset_direction * segment_ctx.get_width() * segment_ctx.get_v_ortho();
-                segment_begin_pos + (scalar * segment_ctx.get_v()) + offset
+                let offset =
+                    offset_direction * segment_ctx.get_width() * polyline.get_normal(scalar);
+                polyline.sample(scalar) + offset
             }
-            actor::ActorContext::OnRoadSegment { pos_param, lane_ctx, actor } => {
-                let (lane_begin_pos, _) = lane_ctx.get_pos();
-                lane_begin_pos + *pos_param * lane_ctx.get_v()
+            actor::ActorContext::OnRoadSegment { pos_param, lane_ctx, .. } => {
+                lane_ctx.get_polyline()?.sample(*pos_param)
             }
-            actor::ActorContext::OnRoadJunction { pos_param, lane_ctx, actor } => {
-                let curve = lane_ctx.get_curve().sample(*pos_param);
+            actor::ActorContext::OnRoadJunction { pos_param, lane_ctx, .. } => {
+                let curve = lane_ctx.get_curve()?.sample(lane_ctx.get_curve_param(*pos_param)?);
                 Point2::new(curve.x, curve.y)
             },
-        }
+        })
     }
 }
 
 pub trait LineLike {
     fn get_width(&self) -> f64;
-    fn get_pos(&self) -> (Pos, Pos);
-    fn get_midpoint(&self) -> Pos {
-        self.get_pos().0 + (0.5 * self.get_v())
+    fn get_pos(&self) -> Result<(Pos, Pos), RoutieError>;
+}
+
+/// Piecewise linear path, parameterized by fraction of its arc length
+#[derive(Debug, Clone)]
+pub struct Polyline(Vec<Pos>);
+
+impl Polyline {
+    pub fn new(points: Vec<Pos>) -> Self {
+        assert!(points.len() >= 2);
+        Self(points)
+    }
+
+    pub fn get_points(&self) -> &[Pos] {
+        &self.0
+    }
+
+    pub fn get_length(&self) -> f64 {
+        self.0.windows(2).map(|leg| (leg[1] - leg[0]).norm()).sum()
+    }
+
+    /// index of the leg at `pos_param`, and how far along that leg it is
+    fn locate(&self, pos_param: road::PosParam) -> (usize, f64) {
+        let last_leg_idx = self.0.len() - 2;
+        let mut remaining = pos_param.clamp(0.0, 1.0) * self.get_length();
+        for leg_idx in 0..last_leg_idx {
+            let leg_length = (self.0[leg_idx + 1] - self.0[leg_idx]).norm();
+            if remaining <= leg_length {
+                return (leg_idx, if leg_length > 0.0 { remaining / leg_length } else { 0.0 });
+            }
+            remaining -= leg_length;
+        }
+        let leg_length = (self.0[last_leg_idx + 1] - self.0[last_leg_idx]).norm();
+        (last_leg_idx, if leg_length > 0.0 { (remaining / leg_length).min(1.0) } else { 1.0 })
     }
-    fn get_v(&self) -> Vector {
-        let (begin_pos, end_pos) = self.get_pos();
-        end_pos - begin_pos
+
+    pub fn sample(&self, pos_param: road::PosParam) -> Pos {
+        let (leg_idx, leg_param) = self.locate(pos_param);
+        self.0[leg_idx] + leg_param * (self.0[leg_idx + 1] - self.0[leg_idx])
     }
-    fn get_v_norm(&self) -> Vector {
-        self.get_v().normalize()
+
+    pub fn get_tangent(&self, pos_param: road::PosParam) -> Vector {
+        let (leg_idx, _) = self.locate(pos_param);
+        (self.0[leg_idx + 1] - self.0[leg_idx]).normalize()
     }
-    fn get_v_ortho(&self) -> Vector {
+
+    pub fn get_normal(&self, pos_param: road::PosParam) -> Vector {
+        Rotation2::new(FRAC_PI_2) * self.get_tangent(pos_param)
+    }
+
+    /// Parallel polyline `distance` away along the normal, mitered at the shape points
+    pub fn offset(&self, distance: f64) -> Self {
         let rot = Rotation2::new(FRAC_PI_2);
-        rot * self.get_v_norm()
+        let leg_normals: Vec<Vector> =
+            self.0.windows(2).map(|leg| rot * (leg[1] - leg[0]).normalize()).collect();
+        let points = self
+            .0
+            .iter()
+            .enumerate()
+            .map(|(idx, point)| {
+                let normal_before = leg_normals[idx.saturating_sub(1)];
+                let normal_after = leg_normals[idx.min(leg_normals.len() - 1)];
+                let miter = (normal_before + normal_after).normalize();
+                point + (distance / miter.dot(&normal_after)) * miter
+            })
+            .collect();
+        Self(points)
+    }
+
+    pub fn reversed(&self) -> Self {
+        Self(self.0.iter().rev().copied().collect())
+    }
+}
+
+impl<'a> road::SegmentContext<'a> {
+    /// Centerline from junction center to junction center
+    pub fn get_untrimmed_points(&self) -> Result<Vec<Pos>, RoutieError> {
+        let (begin_junction_ctx, end_junction_ctx) = self.get_junctions()?;
+        let mut points = vec![begin_junction_ctx.junction.pos];
+        points.extend(self.segment.get_shape());
+        points.push(end_junction_ctx.junction.pos);
+        Ok(points)
+    }
+
+    /// Centerline from junction to junction through the segment's shape points,
+    /// trimmed to the edge of each junction
+    pub fn get_polyline(&self) -> Result<Polyline, RoutieError> {
+        let (begin_junction_ctx, end_junction_ctx) = self.get_junctions()?;
+        let mut points = self.get_untrimmed_points()?;
+        let last_idx = points.len() - 1;
+        let v_begin_trim =
+            begin_junction_ctx.get_trim(self.id, Backward) * (points[1] - points[0]).normalize();
+        let v_end_trim = end_junction_ctx.get_trim(self.id, Forward)
+            * (points[last_idx - 1] - points[last_idx]).normalize();
+        points[0] += v_begin_trim;
+        points[last_idx] += v_end_trim;
+        Ok(Polyline::new(points))
     }
 }
 
 impl<'a> LineLike for road::SegmentContext<'a> {
     fn get_width(&self) -> f64 {
+        let config = self.network.get_config();
         let total_lane_count = self.segment.forward_lanes.len() + self.segment.backward_lanes.len();
-        (1.0 + (ROAD_SEGMENT_WIGGLE_ROOM_PCT as f64 / 100.0))
-            * ROAD_LANE_WIDTH
+        (1.0 + (config.segment_wiggle_room_pct as f64 / 100.0))
+            * config.lane_width
             * std::cmp::max(total_lane_count, 1) as f64
     }
 
-    fn get_v_norm(&self) -> Vector {
-        let (begin_junction_ctx, end_junction_ctx) = self.get_junctions();
-        (end_junction_ctx.junction.pos - begin_junction_ctx.junction.pos).normalize()
-    }
-
-    fn get_pos(&self) -> (Pos, Pos) {
-        let (begin_junction_ctx, end_junction_ctx) = self.get_junctions();
-        (
-            begin_junction_ctx.junction.pos + (ROAD_JUNCTION_RADIUS * self.get_v_norm()),
-            end_junction_ctx.junction.pos + (-1.0 * ROAD_JUNCTION_RADIUS * self.get_v_norm()),
-        )
+    fn get_pos(&self) -> Result<(Pos, Pos), RoutieError> {
+        let polyline = self.get_polyline()?;
+        let points = polyline.get_points();
+        Ok((points[0], points[points.len() - 1]))
     }
 }
 
 impl<'a> LineLike for road::SegmentLaneContext<'a> {
     fn get_width(&self) -> f64 {
-        ROAD_LANE_WIDTH
-    }
-    fn get_v(&self) -> Vector {
-        let rot = Rotation2::new(match self.lane.direction {
-            Backward => PI,
-            Forward => 0.0,
-        });
-        rot * self.segment_ctx.get_v()
-    }
-    fn get_pos(&self) -> (Pos, Pos) {
-        let (segment_begin_pos, segment_end_pos) = self.segment_ctx.get_pos();
-        let v_lat_offset = {
-            let rank: i32 = self.rank.into();
+        self.segment_ctx.network.get_config().lane_width
+    }
+    fn get_pos(&self) -> Result<(Pos, Pos), RoutieError> {
+        match self.get_cached() {
+            Some(geometry) => Ok(geometry.pos),
+            None => {
+                let polyline = self.compute_polyline()?;
+                let points = polyline.get_points();
+                Ok((points[0], points[points.len() - 1]))
+            }
+        }
+    }
+}
+
+impl<'a> road::SegmentLaneContext<'a> {
+    fn get_cached(&self) -> Option<&'a SegmentLaneGeometry> {
+        let lane = (self.segment_ctx.id, self.direction, self.rank);
+        self.segment_ctx.network.get_geometry_cache()?.get_segment_lane(lane)
+    }
+    pub fn get_length(&self) -> Result<f64, RoutieError> {
+        match self.get_cached() {
+            Some(geometry) => Ok(geometry.length),
+            None => Ok(self.compute_polyline()?.get_length()),
+        }
+    }
+    pub fn get_polyline(&self) -> Result<Cow<'a, Polyline>, RoutieError> {
+        match self.get_cached() {
+            Some(geometry) => Ok(Cow::Borrowed(&geometry.polyline)),
+            None => Ok(Cow::Owned(self.compute_polyline()?)),
+        }
+    }
+    fn compute_polyline(&self) -> Result<Polyline, RoutieError> {
+        let lane = (self.segment_ctx.id, self.direction, self.rank);
+        let lane_width = self.get_width();
+        let lat_offset = {
+            // position among the remaining lanes, in case some were removed
+            let rank = self
+                .segment_ctx
+                .segment
+                .get_lanes(self.direction)
+                .ordinal(&self.rank)
+                .ok_or(RoutieError::UnknownSegmentLane(lane))? as i32;
             let lane_count_from_edge = match self.lane.direction {
                 Backward => self.segment_ctx.segment.backward_lanes.len() as i32 - rank - 1,
                 Forward => self.segment_ctx.segment.backward_lanes.len() as i32 + rank,
             };
-            let v_ortho = self.segment_ctx.get_v_ortho();
-            let v_segment_edge = (-0.5)
-                * ROAD_LANE_WIDTH
+            let segment_edge = (-0.5)
+                * lane_width
                 * (self.segment_ctx.segment.backward_lanes.len()
-                    + self.segment_ctx.segment.forward_lanes.len()) as f64
-                * v_ortho;
-            let v_lane_edge =
-                v_segment_edge + (lane_count_from_edge as f64 * ROAD_LANE_WIDTH * v_ortho);
-            v_lane_edge + (0.5 * ROAD_LANE_WIDTH * v_ortho)
+                    + self.segment_ctx.segment.forward_lanes.len()) as f64;
+            let lane_edge = segment_edge + (lane_count_from_edge as f64 * lane_width);
+            lane_edge + (0.5 * lane_width)
         };
-        match self.lane.direction {
-            Backward => (segment_end_pos + v_lat_offset, segment_begin_pos + v_lat_offset),
-            Forward => (segment_begin_pos + v_lat_offset, segment_end_pos + v_lat_offset),
-        }
+        let polyline = self.segment_ctx.get_polyline()?.offset(lat_offset);
+        Ok(match self.lane.direction {
+            Backward => polyline.reversed(),
+            Forward => polyline,
+        })
     }
 }
 
 impl<'a> road::JunctionLaneContext<'a> {
-    pub fn get_pos(&self) -> (Pos, Pos) {
-        let (input_segment_lane, output_segment_lane) =
-            self.junction_ctx.junction.get_segment_lanes_for_junction_lane(self.id);
-
-        let to_pos = |(segment_id, direction, rank): QualifiedSegmentLaneRank| {
-            let segment = self.junction_ctx.network.segments.get(&segment_id).unwrap();
-            let segment_ctx = SegmentContext::new(self.junction_ctx.network, segment_id, segment);
-            let segment_lane = segment.get_lanes(direction).get(&rank).unwrap();
+    fn get_cached(&self) -> Option<&'a JunctionLaneGeometry> {
+        self.junction_ctx
+            .network
+            .get_geometry_cache()?
+            .get_junction_lane(self.junction_ctx.id, self.id)
+    }
+
+    pub fn get_pos(&self) -> Result<(Pos, Pos), RoutieError> {
+        match self.get_cached() {
+            Some(geometry) => Ok(geometry.pos),
+            None => self.compute_pos(),
+        }
+    }
+
+    pub fn get_curve(&self) -> Result<CubicBezierSegment<f64>, RoutieError> {
+        match self.get_cached() {
+            Some(geometry) => Ok(geometry.curve),
+            None => self.compute_curve(),
+        }
+    }
+
+    pub fn get_length(&self) -> Result<f64, RoutieError> {
+        match self.get_cached() {
+            Some(geometry) => Ok(geometry.length),
+            None => Ok(self.compute_curve()?.approximate_length(self.get_tolerance())),
+        }
+    }
+
+    /// Junction lane `pos_param` is a fraction of arc length, not of the Bezier param,
+    /// so that actors move along turns at a uniform speed
+    pub fn get_curve_param(&self, pos_param: road::PosParam) -> Result<f64, RoutieError> {
+        match self.get_cached() {
+            Some(geometry) => {
+                Ok(lookup_curve_param(&geometry.arc_lengths, pos_param * geometry.length))
+            }
+            None => {
+                let arc_lengths =
+                    build_arc_length_table(&self.compute_curve()?, self.get_tolerance());
+                let length = arc_lengths.last().map_or(0.0, |(_, length)| *length);
+                Ok(lookup_curve_param(&arc_lengths, pos_param * length))
+            }
+        }
+    }
+
+    fn get_tolerance(&self) -> f64 {
+        self.junction_ctx.network.get_config().curve_flattening_tolerance
+    }
+
+    fn compute_pos(&self) -> Result<(Pos, Pos), RoutieError> {
+        let (input_segment_lane, output_segment_lane) = self.get_segment_lanes()?;
+
+        let network = self.junction_ctx.network;
+        let to_pos = |lane: QualifiedSegmentLaneRank| {
+            let (segment_id, direction, rank) = lane;
+            let segment_ctx =
+                SegmentContext::new(network, segment_id, network.get_segment(segment_id)?);
+            let segment_lane = network.get_segment_lane(lane)?;
             SegmentLaneContext::new(&segment_ctx, direction, rank, segment_lane).get_pos()
         };
-        let (_, input_end_pos) = to_pos(input_segment_lane);
-        let (output_begin_pos, _) = to_pos(output_segment_lane);
-        (input_end_pos, output_begin_pos)
+        let (_, input_end_pos) = to_pos(input_segment_lane)?;
+        let (output_begin_pos, _) = to_pos(output_segment_lane)?;
+        Ok((input_end_pos, output_begin_pos))
     }
 
-    // TODO: memoize
-    pub fn get_curve(&self) -> QuadraticBezierSegment<f64> {
+    fn compute_curve(&self) -> Result<CubicBezierSegment<f64>, RoutieError> {
         let to_lyon_point = |p: Pos| lyon_geom::Point::new(p.x, p.y);
         let to_lyon_vector = |v: Vector| lyon_geom::Vector::new(v.x, v.y);
-        let to_line = |(segment_id, direction, rank): QualifiedSegmentLaneRank| {
-            let segment = self.junction_ctx.network.segments.get(&segment_id).unwrap();
-            let segment_ctx = SegmentContext::new(self.junction_ctx.network, segment_id, segment);
-            let segment_lane = segment_ctx.segment.get_lanes(direction).get(&rank).unwrap();
+        // tangent line at `pos_param` along the segment lane
+        let network = self.junction_ctx.network;
+        let to_line = |lane: QualifiedSegmentLaneRank, pos_param| {
+            let (segment_id, direction, rank) = lane;
+            let segment_ctx =
+                SegmentContext::new(network, segment_id, network.get_segment(segment_id)?);
+            let segment_lane = network.get_segment_lane(lane)?;
             let segment_lane_ctx =
                 SegmentLaneContext::new(&segment_ctx, direction, rank, segment_lane);
-            lyon_geom::Line {
-                point: to_lyon_point(segment_lane_ctx.get_pos().0),
-                vector: to_lyon_vector(segment_lane_ctx.get_v()),
-            }
+            let polyline = segment_lane_ctx.get_polyline()?;
+            Ok(lyon_geom::Line {
+                point: to_lyon_point(polyline.sample(pos_param)),
+                vector: to_lyon_vector(polyline.get_tangent(pos_param)),
+            })
         };
 
-        let (begin_pos, end_pos) = self.get_pos();
-        let (input_segment_lane, output_segment_lane) =
-            self.junction_ctx.junction.get_segment_lanes_for_junction_lane(self.id);
-        let input_lane_line = to_line(input_segment_lane);
-        let output_lane_line = to_line(output_segment_lane);
+        let (begin_pos, end_pos) = self.get_pos()?;
+        let (input_segment_lane, output_segment_lane) = self.get_segment_lanes()?;
+        let input_lane_line = to_line(input_segment_lane, 1.0)?;
+        let output_lane_line = to_line(output_segment_lane, 0.0)?;
+
+        // u-turn: lines are antiparallel, so bulge out past the end of the input lane
+        if input_lane_line.vector.dot(output_lane_line.vector) < 0.0
+            && input_lane_line.intersection(&output_lane_line).is_none()
+        {
+            // 4/3 * r is the usual cubic approximation of a semicircle of radius r
+            let reach = (2.0 / 3.0) * (end_pos - begin_pos).norm();
+            let input_v = input_lane_line.vector.normalize();
+            let output_v = output_lane_line.vector.normalize();
+            return Ok(CubicBezierSegment {
+                from: to_lyon_point(begin_pos),
+                ctrl1: to_lyon_point(begin_pos) + input_v * reach,
+                ctrl2: to_lyon_point(end_pos) - output_v * reach,
+                to: to_lyon_point(end_pos),
+            });
+        }
+
         let intersect_pos = match input_lane_line.intersection(&output_lane_line) {
             None => begin_pos + 0.5 * (end_pos - begin_pos),
             Some(p) => Pos::new(p.x, p.y),
         };
-        QuadraticBezierSegment {
+        Ok(QuadraticBezierSegment {
             from: to_lyon_point(begin_pos),
             ctrl: to_lyon_point(intersect_pos),
             to: to_lyon_point(end_pos),
         }
+        .to_cubic())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::road::{Network, UTurnPolicy};
+
+    fn is_convex(polygon: &[Pos]) -> bool {
+        (0..polygon.len()).all(|idx| {
+            let a = polygon[idx];
+            let b = polygon[(idx + 1) % polygon.len()];
+            let c = polygon[(idx + 2) % polygon.len()];
+            (b - a).perp(&(c - b)) > 0.0
+        })
+    }
+
+    #[test]
+    fn convex_hull_drops_dents_and_duplicates() {
+        let points = [(0.0, 0.0), (2.0, 0.0), (1.0, 0.5), (2.0, 2.0), (0.0, 2.0), (2.0, 0.0)];
+        let hull = convex_hull(points.iter().map(|(x, y)| Pos::new(*x, *y)).collect());
+        let expected = [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)];
+        assert_eq!(hull, expected.iter().map(|(x, y)| Pos::new(*x, *y)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn degenerate_segments_leave_footprints_convex() {
+        let mut network = Network::new();
+        let west = network.add_junction(Pos::new(0.2, 0.5));
+        let east = network.add_junction(Pos::new(0.8, 0.5));
+        let north = network.add_junction(Pos::new(0.5, 0.9));
+        let coincident = network.add_junction(Pos::new(0.8, 0.5));
+        for (begin_id, end_id) in [(west, east), (north, east), (west, west), (east, coincident)] {
+            let (_, segment) = network.add_segment(begin_id, end_id).unwrap();
+            segment.add_lane(Forward);
+            segment.add_lane(Backward);
+        }
+        network.connect_junctions(UTurnPolicy::DeadEnds);
+
+        for (junction_id, junction) in network.junctions.enumerate() {
+            let footprint =
+                road::JunctionContext::new(&network, junction_id, junction).get_footprint();
+            assert!(is_convex(&footprint), "{:?}: {:?}", junction_id, footprint);
+        }
     }
 }
@@ -5,131 +5,483 @@ pub trait CloneEmpty {
 #[macro_use]
 pub mod seq_indexed_store {
     use super::CloneEmpty;
+    use crate::error::RoutieError;
+    use serde::{Deserialize, Serialize};
     use std::marker::PhantomData;
 
-    #[derive(Debug)]
+    /// Slot index plus the generation of the slot it was issued for, so that an ID
+    /// outliving its value can't be mistaken for whatever reuses the slot
+    pub trait GenerationalIndex: Copy {
+        fn new(idx: usize, generation: u32) -> Self;
+        fn idx(&self) -> usize;
+        fn generation(&self) -> u32;
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Slot<T> {
+        generation: u32,
+        val: Option<T>,
+    }
+
+    /// Removal leaves a tombstone, so the IDs of the remaining values stay valid.
+    /// If slots are reused, the reissued IDs get a new generation.
+    #[derive(Debug, Serialize, Deserialize)]
     pub struct SeqIndexedStore<U, T> {
         index_type: PhantomData<U>,
-        data: Vec<T>,
+        data: Vec<Slot<T>>,
+        live_count: usize,
+        /// `None` if slots are never reused, which keeps IDs in insertion order
+        free_slots: Option<Vec<usize>>,
     }
 
     impl<U, T> SeqIndexedStore<U, T>
     where
-        U: From<usize> + Into<usize> + Copy,
+        U: GenerationalIndex,
         T: CloneEmpty,
     {
         pub fn new() -> Self {
-            Self { index_type: PhantomData, data: Vec::new() }
+            Self { index_type: PhantomData, data: Vec::new(), live_count: 0, free_slots: None }
+        }
+        pub fn new_reusing_slots() -> Self {
+            Self { free_slots: Some(Vec::new()), ..Self::new() }
         }
         pub fn clone_empty(&self) -> Self {
             Self {
                 index_type: self.index_type,
-                data: self.data.iter().map(|x| x.clone_empty()).collect(),
+                data: self
+                    .data
+                    .iter()
+                    .map(|slot| Slot {
+                        generation: slot.generation,
+                        val: slot.val.as_ref().map(|x| x.clone_empty()),
+                    })
+                    .collect(),
+                live_count: self.live_count,
+                free_slots: self.free_slots.clone(),
             }
         }
         pub fn push(&mut self, val: T) -> U {
-            let id = self.data.len();
-            self.data.push(val);
-            U::from(id)
+            self.live_count += 1;
+            if let Some(idx) = self.free_slots.as_mut().and_then(|free_slots| free_slots.pop()) {
+                let slot = &mut self.data[idx];
+                slot.generation += 1;
+                slot.val = Some(val);
+                return U::new(idx, slot.generation);
+            }
+            self.data.push(Slot { generation: 0, val: Some(val) });
+            U::new(self.data.len() - 1, 0)
+        }
+        pub fn remove(&mut self, id: &U) -> Option<T> {
+            let val = self.get_slot_mut(id)?.val.take()?;
+            self.live_count -= 1;
+            if let Some(free_slots) = self.free_slots.as_mut() {
+                free_slots.push(id.idx());
+            }
+            Some(val)
+        }
+        fn get_slot(&self, id: &U) -> Option<&Slot<T>> {
+            self.data.get(id.idx()).filter(|slot| slot.generation == id.generation())
+        }
+        fn get_slot_mut(&mut self, id: &U) -> Option<&mut Slot<T>> {
+            self.data.get_mut(id.idx()).filter(|slot| slot.generation == id.generation())
         }
         pub fn get(&self, id: &U) -> Option<&T> {
-            self.data.get(Into::<usize>::into(*id))
+            self.get_slot(id)?.val.as_ref()
         }
         pub fn get_mut(&mut self, id: &U) -> Option<&mut T> {
-            self.data.get_mut(Into::<usize>::into(*id))
+            self.get_slot_mut(id)?.val.as_mut()
+        }
+        /// Like `get`, but tells a removed value apart from one that never existed
+        pub fn try_get(&self, id: &U) -> Result<&T, RoutieError> {
+            match self.data.get(id.idx()) {
+                None => Err(RoutieError::InvalidId),
+                Some(slot) if slot.generation != id.generation() => Err(RoutieError::StaleId),
+                Some(slot) => slot.val.as_ref().ok_or(RoutieError::StaleId),
+            }
+        }
+        pub fn try_get_mut(&mut self, id: &U) -> Result<&mut T, RoutieError> {
+            match self.data.get_mut(id.idx()) {
+                None => Err(RoutieError::InvalidId),
+                Some(slot) if slot.generation != id.generation() => Err(RoutieError::StaleId),
+                Some(slot) => slot.val.as_mut().ok_or(RoutieError::StaleId),
+            }
         }
         pub fn len(&self) -> usize {
-            self.data.len()
+            self.live_count
+        }
+        pub fn is_empty(&self) -> bool {
+            self.live_count == 0
         }
         pub fn first_idx(&self) -> U {
-            U::from(0)
+            self.enumerate().next().unwrap().0
         }
         pub fn last_idx(&self) -> U {
-            U::from(self.len() - 1)
+            self.enumerate().last().unwrap().0
+        }
+        /// position of `id` among the values that haven't been removed
+        pub fn ordinal(&self, id: &U) -> Option<usize> {
+            self.get(id)?;
+            Some(self.data[..id.idx()].iter().filter(|slot| slot.val.is_some()).count())
         }
 
         pub fn enumerate(&self) -> impl Iterator<Item = (U, &T)> {
-            self.data.iter().enumerate().map(|val| (U::from(val.0), val.1))
+            self.data.iter().enumerate().filter_map(|(idx, slot)| {
+                slot.val.as_ref().map(|val| (U::new(idx, slot.generation), val))
+            })
         }
 
         pub fn enumerate_mut(&mut self) -> impl Iterator<Item = (U, &mut T)> {
-            self.data.iter_mut().enumerate().map(|val| (U::from(val.0), val.1))
+            self.data.iter_mut().enumerate().filter_map(|(idx, slot)| {
+                let generation = slot.generation;
+                slot.val.as_mut().map(|val| (U::new(idx, generation), val))
+            })
+        }
+    }
+
+    impl<U, T> Default for SeqIndexedStore<U, T>
+    where
+        U: GenerationalIndex,
+        T: CloneEmpty,
+    {
+        fn default() -> Self {
+            Self::new()
         }
     }
 
     macro_rules! define_index_type {
         ($name:ident) => {
-            #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
-            pub struct $name(usize);
+            /// ordered by slot, then generation
+            #[derive(
+                PartialEq,
+                Eq,
+                PartialOrd,
+                Ord,
+                Hash,
+                Clone,
+                Copy,
+                serde::Serialize,
+                serde::Deserialize,
+            )]
+            pub struct $name {
+                idx: usize,
+                generation: u32,
+            }
+            /// e.g. `SegmentId(3)`, or `SegmentId(3/1)` for the second value in slot 3
+            impl std::fmt::Debug for $name {
+                fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    match self.generation {
+                        0 => write!(f, "{}({})", stringify!($name), self.idx),
+                        generation => {
+                            write!(f, "{}({}/{})", stringify!($name), self.idx, generation)
+                        }
+                    }
+                }
+            }
+            impl $crate::util::seq_indexed_store::GenerationalIndex for $name {
+                fn new(idx: usize, generation: u32) -> $name {
+                    $name { idx, generation }
+                }
+                fn idx(&self) -> usize {
+                    self.idx
+                }
+                fn generation(&self) -> u32 {
+                    self.generation
+                }
+            }
+            /// first generation, i.e. as issued by a store that never reuses slots
             impl From<usize> for $name {
-                fn from(id: usize) -> $name {
-                    $name(id)
+                fn from(idx: usize) -> $name {
+                    $name { idx, generation: 0 }
                 }
             }
             impl From<$name> for usize {
                 fn from(id: $name) -> usize {
-                    id.0
+                    id.idx
                 }
             }
             impl From<$name> for u32 {
                 fn from(id: $name) -> u32 {
-                    id.0 as u32
+                    id.idx as u32
                 }
             }
             impl From<$name> for i32 {
                 fn from(id: $name) -> i32 {
-                    id.0 as i32
+                    id.idx as i32
                 }
             }
         };
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        define_index_type!(TestId);
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct Value(u32);
+
+        impl CloneEmpty for Value {
+            fn clone_empty(&self) -> Self {
+                self.clone()
+            }
+        }
+
+        #[test]
+        fn reused_slot_makes_old_id_stale() {
+            let mut store: SeqIndexedStore<TestId, Value> = SeqIndexedStore::new_reusing_slots();
+            let a = store.push(Value(1));
+            let b = store.push(Value(2));
+            assert_eq!(store.remove(&a), Some(Value(1)));
+            assert_eq!(store.remove(&a), None);
+
+            let c = store.push(Value(3));
+            assert_eq!(c.idx(), a.idx());
+            assert_ne!(c, a);
+            assert_eq!(store.try_get(&a), Err(RoutieError::StaleId));
+            assert_eq!(store.get(&a), None);
+            assert_eq!(store.try_get(&c), Ok(&Value(3)));
+            assert_eq!(store.try_get(&b), Ok(&Value(2)));
+            assert_eq!(store.len(), 2);
+        }
+
+        #[test]
+        fn removed_id_is_stale_without_reuse() {
+            let mut store: SeqIndexedStore<TestId, Value> = SeqIndexedStore::new();
+            let a = store.push(Value(1));
+            store.remove(&a);
+            assert_eq!(store.try_get(&a), Err(RoutieError::StaleId));
+            assert_eq!(store.try_get_mut(&a), Err(RoutieError::StaleId));
+
+            let b = store.push(Value(2));
+            assert_ne!(b.idx(), a.idx());
+            assert_eq!(store.try_get(&TestId::from(5)), Err(RoutieError::InvalidId));
+        }
+
+        #[test]
+        fn enumeration_skips_removed() {
+            let mut store: SeqIndexedStore<TestId, Value> = SeqIndexedStore::new_reusing_slots();
+            let ids: Vec<TestId> = (0..4).map(|x| store.push(Value(x))).collect();
+            store.remove(&ids[0]);
+            store.remove(&ids[2]);
+
+            let values: Vec<(TestId, &Value)> = store.enumerate().collect();
+            assert_eq!(values, vec![(ids[1], &Value(1)), (ids[3], &Value(3))]);
+            assert_eq!(store.enumerate_mut().count(), 2);
+            assert_eq!(store.first_idx(), ids[1]);
+            assert_eq!(store.last_idx(), ids[3]);
+            assert_eq!(store.ordinal(&ids[3]), Some(1));
+            assert_eq!(store.ordinal(&ids[2]), None);
+
+            // copies keep the generations, so IDs stay valid in them
+            let copy = store.clone_empty();
+            assert_eq!(copy.try_get(&ids[3]), Ok(&Value(3)));
+            assert_eq!(copy.try_get(&ids[0]), Err(RoutieError::StaleId));
+        }
+    }
 }
 
 pub mod ordered_skip_map {
     use std::{cmp::Ordering, ops::Bound};
 
-    use skiplist::OrderedSkipList;
+    use skiplist::SkipMap;
+
+    /// Keys with an order that leaves none out, unlike `PartialOrd`
+    pub trait TotalOrd: Copy {
+        fn total_cmp(&self, other: &Self) -> Ordering;
+    }
+
+    impl TotalOrd for f64 {
+        fn total_cmp(&self, other: &Self) -> Ordering {
+            f64::total_cmp(self, other)
+        }
+    }
+
+    /// Orders values sharing a key
+    pub trait TieBreak {
+        type Tie: Ord + Copy;
+        fn get_tie(&self) -> Self::Tie;
+    }
+
+    /// `Before` and `After` only ever appear in bounds, around every value with the same key
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    enum Tie<T> {
+        Before,
+        At(T),
+        After,
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    struct Entry<K, T> {
+        key: K,
+        tie: Tie<T>,
+    }
+
+    impl<K: TotalOrd, T: Ord> Ord for Entry<K, T> {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.key.total_cmp(&other.key).then_with(|| self.tie.cmp(&other.tie))
+        }
+    }
+    impl<K: TotalOrd, T: Ord> PartialOrd for Entry<K, T> {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl<K: TotalOrd, T: Ord> PartialEq for Entry<K, T> {
+        fn eq(&self, other: &Self) -> bool {
+            self.cmp(other) == Ordering::Equal
+        }
+    }
+    impl<K: TotalOrd, T: Ord> Eq for Entry<K, T> {}
 
+    /// Ordered by key, then by `TieBreak`, so values sharing a key come out the same way
+    /// however they went in. A value is identified by its key and tie together.
     #[derive(Debug)]
-    pub struct OrderedSkipMap<K, V> {
-        null_value_builder: fn() -> V,
-        data: OrderedSkipList<(K, V)>,
+    pub struct OrderedSkipMap<K, V: TieBreak> {
+        data: SkipMap<Entry<K, V::Tie>, V>,
     }
 
-    impl<K: Copy + PartialOrd, V> OrderedSkipMap<K, V> {
-        pub fn new(null_value_builder: fn() -> V) -> Self {
-            Self {
-                null_value_builder,
-                data: unsafe {
-                    OrderedSkipList::with_comp(|a: &(K, V), b: &(K, V)| {
-                        if a.0 < b.0 {
-                            Ordering::Less
-                        } else {
-                            Ordering::Greater
-                        }
-                    })
-                },
-            }
+    impl<K: TotalOrd, V: TieBreak> OrderedSkipMap<K, V> {
+        pub fn new() -> Self {
+            Self { data: SkipMap::new() }
+        }
+        fn entry(key: K, tie: Tie<V::Tie>) -> Entry<K, V::Tie> {
+            Entry { key, tie }
+        }
+        fn unwrap_item<'a>((entry, value): (&'a Entry<K, V::Tie>, &'a V)) -> (&'a K, &'a V) {
+            (&entry.key, value)
+        }
+
+        /// Replaces any value with the same key and tie, and returns it
+        pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+            let tie = value.get_tie();
+            self.data.insert(Self::entry(key, Tie::At(tie)), value)
+        }
+        pub fn remove(&mut self, key: K, tie: V::Tie) -> Option<V> {
+            self.data.remove(&Self::entry(key, Tie::At(tie)))
+        }
+        pub fn get(&self, key: K, tie: V::Tie) -> Option<&V> {
+            self.data.get(&Self::entry(key, Tie::At(tie)))
+        }
+        pub fn get_mut(&mut self, key: K, tie: V::Tie) -> Option<&mut V> {
+            self.data.get_mut(&Self::entry(key, Tie::At(tie)))
+        }
+        pub fn len(&self) -> usize {
+            self.data.len()
+        }
+        pub fn is_empty(&self) -> bool {
+            self.data.is_empty()
+        }
+        pub fn clear(&mut self) {
+            self.data.clear();
+        }
+
+        /// The first value with a key greater than `key`, e.g. the leader of an actor at `key`
+        pub fn next_after(&self, key: K) -> Option<(&K, &V)> {
+            let after = Self::entry(key, Tie::After);
+            self.data.range(Bound::Excluded(&after), Bound::Unbounded).next().map(Self::unwrap_item)
+        }
+        /// The last value with a key less than `key`, e.g. the follower of an actor at `key`
+        pub fn prev_before(&self, key: K) -> Option<(&K, &V)> {
+            let before = Self::entry(key, Tie::Before);
+            self.data
+                .range(Bound::Unbounded, Bound::Excluded(&before))
+                .next_back()
+                .map(Self::unwrap_item)
         }
-        pub fn insert(&mut self, key: K, value: V) {
-            self.data.insert((key, value));
+        /// Keys from `min` to `max`, both included
+        pub fn enumerate_range(&self, min: K, max: K) -> impl Iterator<Item = (&K, &V)> {
+            let (min, max) = (Self::entry(min, Tie::Before), Self::entry(max, Tie::After));
+            self.data.range(Bound::Included(&min), Bound::Included(&max)).map(Self::unwrap_item)
         }
+        pub fn enumerate(&self) -> impl Iterator<Item = (&K, &V)> {
+            self.data.iter().map(Self::unwrap_item)
+        }
+    }
+
+    impl<K: TotalOrd, V: TieBreak> Default for OrderedSkipMap<K, V> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[derive(Debug, PartialEq)]
+        struct Occupant(u32);
 
-        // TODO: is this going to work?
-        pub fn remove(&mut self, key: K) -> Option<V> {
-            match self.data.remove(&(key, (self.null_value_builder)())) {
-                Some((_, val)) => Some(val),
-                None => None,
+        impl TieBreak for Occupant {
+            type Tie = u32;
+            fn get_tie(&self) -> u32 {
+                self.0
             }
         }
-        pub fn enumerate_range(&self, min: K, max: K) -> impl Iterator<Item = &(K, V)> {
-            self.data.range(
-                Bound::Included(&(min, (self.null_value_builder)())),
-                Bound::Included(&(max, (self.null_value_builder)())),
-            )
+
+        /// Three at 0.5, inserted out of order, between one at 0.2 and one at 0.8
+        fn occupied() -> OrderedSkipMap<f64, Occupant> {
+            let mut map = OrderedSkipMap::new();
+            for (key, id) in [(0.5, 3), (0.8, 0), (0.5, 1), (0.2, 9), (0.5, 2)] {
+                assert_eq!(map.insert(key, Occupant(id)), None);
+            }
+            map
+        }
+
+        fn ids<'a>(items: impl Iterator<Item = (&'a f64, &'a Occupant)>) -> Vec<u32> {
+            items.map(|(_, occupant)| occupant.0).collect()
         }
-        pub fn enumerate(&self) -> impl Iterator<Item = &(K, V)> {
-            self.data.range(Bound::Unbounded, Bound::Unbounded)
+
+        #[test]
+        fn equal_keys_ordered_by_tie() {
+            let map = occupied();
+            assert_eq!(ids(map.enumerate()), vec![9, 1, 2, 3, 0]);
+            assert_eq!(ids(map.enumerate_range(0.5, 0.5)), vec![1, 2, 3]);
+            assert_eq!(ids(map.enumerate_range(0.3, 1.0)), vec![1, 2, 3, 0]);
+        }
+
+        #[test]
+        fn removes_one_of_several_at_a_key() {
+            let mut map = occupied();
+            assert_eq!(map.remove(0.5, 2), Some(Occupant(2)));
+            assert_eq!(map.remove(0.5, 2), None);
+            // right tie, wrong key
+            assert_eq!(map.remove(0.4, 1), None);
+            assert_eq!(map.get(0.5, 1), Some(&Occupant(1)));
+            assert_eq!(map.get(0.5, 3), Some(&Occupant(3)));
+            assert_eq!(map.len(), 4);
+            assert_eq!(ids(map.enumerate()), vec![9, 1, 3, 0]);
+
+            // reinserting the same key and tie replaces
+            assert_eq!(map.insert(0.5, Occupant(1)), Some(Occupant(1)));
+            assert_eq!(map.len(), 4);
+        }
+
+        #[test]
+        fn neighbours_skip_equal_keys() {
+            let map = occupied();
+            assert_eq!(map.next_after(0.5), Some((&0.8, &Occupant(0))));
+            assert_eq!(map.prev_before(0.5), Some((&0.2, &Occupant(9))));
+            assert_eq!(map.next_after(0.2), Some((&0.5, &Occupant(1))));
+            assert_eq!(map.prev_before(0.8), Some((&0.5, &Occupant(3))));
+            // at and beyond the ends
+            assert_eq!(map.next_after(0.8), None);
+            assert_eq!(map.prev_before(0.2), None);
+            assert_eq!(map.next_after(0.0), Some((&0.2, &Occupant(9))));
+            assert_eq!(map.prev_before(1.0), Some((&0.8, &Occupant(0))));
+
+            let empty: OrderedSkipMap<f64, Occupant> = OrderedSkipMap::new();
+            assert_eq!(empty.next_after(0.5), None);
+            assert_eq!(empty.prev_before(0.5), None);
+        }
+
+        #[test]
+        fn incomparable_keys_are_still_found() {
+            let mut map = occupied();
+            map.insert(f64::NAN, Occupant(4));
+            assert_eq!(map.len(), 6);
+            assert_eq!(map.remove(f64::NAN, 4), Some(Occupant(4)));
+            assert_eq!(ids(map.enumerate()), vec![9, 1, 2, 3, 0]);
         }
     }
 }
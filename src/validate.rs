@@ -0,0 +1,401 @@
+use std::fmt;
+
+use crate::{
+    actor::{self, Actor, Agendum},
+    analysis::LaneGraph,
+    road::{
+        Direction::{Backward, Forward},
+        JunctionContext, JunctionId, Network, QualifiedSegmentLaneRank, SegmentContext, SegmentId,
+    },
+    spatial,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationIssue {
+    IsolatedJunction(JunctionId),
+    /// not linked to existing junctions at both ends, so it's left out of every other check
+    UnlinkedSegment(SegmentId),
+    /// begins and ends at the same junction, so it's left out of every later check
+    SelfLoopSegment(SegmentId),
+    /// its junctions and shape points are all at the same position, so it has no direction,
+    /// and it's left out of every later check
+    ZeroLengthSegment(SegmentId),
+    SegmentWithoutLanes(SegmentId),
+    /// no junction lane leads out of it
+    DeadEndLane(QualifiedSegmentLaneRank),
+    /// no junction lane leads into it
+    UnreachableLane(QualifiedSegmentLaneRank),
+    /// off-road on a segment with no lanes, so it can never get on the road. Reported instead
+    /// of `SegmentWithoutLanes`.
+    ActorWithoutLane(SegmentId),
+    /// an actor on `segment_id` has an agendum to travel to a segment with no lanes
+    DestinationWithoutLane {
+        segment_id: SegmentId,
+        destination: SegmentId,
+    },
+    /// an actor on `segment_id` has an agendum to travel to a segment that doesn't exist
+    UnknownDestination {
+        segment_id: SegmentId,
+        destination: SegmentId,
+    },
+    /// an actor on `segment_id` has an agendum to travel to a segment it can't get to
+    /// from where it will be at that point
+    UnreachableDestination {
+        segment_id: SegmentId,
+        destination: SegmentId,
+    },
+    OverlappingJunctions(JunctionId, JunctionId),
+}
+
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ValidationIssue::*;
+        match self {
+            IsolatedJunction(id) => {
+                write!(f, "{:?} has no linked segments; link a segment or remove it", id)
+            }
+            UnlinkedSegment(id) => write!(
+                f,
+                "{:?} isn't linked to junctions at both ends; remove it or add it with `add_segment`",
+                id
+            ),
+            SelfLoopSegment(id) => write!(
+                f,
+                "{:?} begins and ends at the same junction; add an intermediate junction",
+                id
+            ),
+            ZeroLengthSegment(id) => {
+                write!(f, "{:?} has no length; move its junctions apart or give it a shape", id)
+            }
+            SegmentWithoutLanes(id) => {
+                write!(f, "{:?} has no lanes; add a lane or remove the segment", id)
+            }
+            DeadEndLane(lane) => write!(
+                f,
+                "{:?} leads nowhere; link another segment at its end or allow U-turns there",
+                lane
+            ),
+            UnreachableLane(lane) => write!(
+                f,
+                "{:?} can't be entered from any junction; only actors starting on it can use it",
+                lane
+            ),
+            ActorWithoutLane(id) => {
+                write!(f, "an actor is on {:?}, which has no lanes; add a lane to it", id)
+            }
+            DestinationWithoutLane { segment_id, destination } => write!(
+                f,
+                "an actor on {:?} is headed to {:?}, which has no lanes; add a lane to it",
+                segment_id, destination
+            ),
+            UnknownDestination { segment_id, destination } => write!(
+                f,
+                "an actor on {:?} is headed to {:?}, which doesn't exist",
+                segment_id, destination
+            ),
+            UnreachableDestination { segment_id, destination } => write!(
+                f,
+                "an actor on {:?} is headed to {:?}, but no route leads there; check how junctions connect their lanes",
+                segment_id, destination
+            ),
+            OverlappingJunctions(a, b) => {
+                write!(f, "{:?} and {:?} overlap; move them apart or shrink their radii", a, b)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for issue in &self.issues {
+            writeln!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+impl Network {
+    /// Checks for problems that would otherwise show up as panics or stuck actors
+    /// during simulation. Meant to be run after `connect_junctions`.
+    pub fn validate(&self) -> ValidationReport {
+        let mut issues = Vec::new();
+
+        for (junction_id, _) in self.junctions.enumerate() {
+            if self.get_junction_segments(junction_id).next().is_none() {
+                issues.push(ValidationIssue::IsolatedJunction(junction_id));
+            }
+        }
+
+        let lane_graph = LaneGraph::new(self);
+
+        for (segment_id, segment) in self.segments.enumerate() {
+            let (begin_id, end_id) = match self.get_segment_junctions(segment_id) {
+                Ok((begin_id, end_id))
+                    if self.get_junction(begin_id).is_ok() && self.get_junction(end_id).is_ok() =>
+                {
+                    (begin_id, end_id)
+                }
+                _ => {
+                    issues.push(ValidationIssue::UnlinkedSegment(segment_id));
+                    continue;
+                }
+            };
+            // checked first, since the geometry of these segments is degenerate
+            if begin_id == end_id {
+                issues.push(ValidationIssue::SelfLoopSegment(segment_id));
+                continue;
+            }
+            let points = SegmentContext::new(self, segment_id, segment).get_untrimmed_points();
+            if points.is_ok_and(|points| points.windows(2).all(|leg| leg[0] == leg[1])) {
+                issues.push(ValidationIssue::ZeroLengthSegment(segment_id));
+                continue;
+            }
+            if segment.forward_lanes.len() + segment.backward_lanes.len() == 0 {
+                // the more specific issue, if there is one
+                if segment
+                    .forward_actors
+                    .enumerate()
+                    .chain(segment.backward_actors.enumerate())
+                    .next()
+                    .is_some()
+                {
+                    issues.push(ValidationIssue::ActorWithoutLane(segment_id));
+                } else {
+                    issues.push(ValidationIssue::SegmentWithoutLanes(segment_id));
+                }
+            }
+            for direction in [Forward, Backward] {
+                for (rank, _) in segment.get_lanes(direction).enumerate() {
+                    let lane = (segment_id, direction, rank);
+                    if lane_graph.get_successors(lane).is_empty() {
+                        issues.push(ValidationIssue::DeadEndLane(lane));
+                    }
+                    if lane_graph.get_predecessors(lane).is_empty() {
+                        issues.push(ValidationIssue::UnreachableLane(lane));
+                    }
+                }
+            }
+        }
+
+        for (segment_id, start_lane, actor) in self.enumerate_actors() {
+            // each trip starts where the last one ended
+            let mut current_lane = start_lane;
+            for agendum in actor.get_agenda().iter().rev() {
+                if let Agendum::TravelTo {
+                    segment_id: destination,
+                    segment_side: destination_side,
+                    pos_param: destination_pos_param,
+                } = agendum
+                {
+                    let location = self.get_segment(*destination).map(|segment| {
+                        let segment_ctx = SegmentContext::new(self, *destination, segment);
+                        actor::to_on_road_location(
+                            &segment_ctx,
+                            *destination_side,
+                            *destination_pos_param,
+                        )
+                    });
+                    match location {
+                        Err(_) => issues.push(ValidationIssue::UnknownDestination {
+                            segment_id,
+                            destination: *destination,
+                        }),
+                        Ok(Err(_)) => issues.push(ValidationIssue::DestinationWithoutLane {
+                            segment_id,
+                            destination: *destination,
+                        }),
+                        Ok(Ok((direction, rank, _))) => {
+                            let destination_lane = (*destination, direction, rank);
+                            if let Some(lane) = current_lane {
+                                if !lane_graph.is_reachable(lane, destination_lane) {
+                                    issues.push(ValidationIssue::UnreachableDestination {
+                                        segment_id,
+                                        destination: *destination,
+                                    });
+                                }
+                            }
+                            current_lane = Some(destination_lane);
+                            continue;
+                        }
+                    }
+                    // no telling where the actor will be after this
+                    current_lane = None;
+                }
+            }
+        }
+
+        let footprints: Vec<(JunctionId, Vec<spatial::Pos>)> = self
+            .junctions
+            .enumerate()
+            .map(|(id, junction)| (id, JunctionContext::new(self, id, junction).get_footprint()))
+            .collect();
+        for (idx, (a_id, a_footprint)) in footprints.iter().enumerate() {
+            for (b_id, b_footprint) in &footprints[idx + 1..] {
+                if spatial::polygons_overlap(a_footprint, b_footprint) {
+                    issues.push(ValidationIssue::OverlappingJunctions(*a_id, *b_id));
+                }
+            }
+        }
+
+        ValidationReport { issues }
+    }
+
+    /// Every actor on a segment, whether off-road or on one of its lanes, along with the lane
+    /// it's on or will get onto. Actors partway through a trip are taken to start from their
+    /// current lane.
+    fn enumerate_actors(
+        &self,
+    ) -> impl Iterator<Item = (SegmentId, Option<QualifiedSegmentLaneRank>, &Actor)> {
+        self.segments.enumerate().flat_map(move |(segment_id, segment)| {
+            let off_road =
+                [(Forward, &segment.forward_actors), (Backward, &segment.backward_actors)]
+                    .into_iter()
+                    .flat_map(move |(side, actors)| {
+                        actors.enumerate().map(move |(pos_param, actor)| {
+                            let segment_ctx = SegmentContext::new(self, segment_id, segment);
+                            let start_lane =
+                                actor::to_on_road_location(&segment_ctx, side, *pos_param)
+                                    .ok()
+                                    .map(|(direction, rank, _)| (segment_id, direction, rank));
+                            (segment_id, start_lane, actor)
+                        })
+                    });
+            let on_road = [Forward, Backward].into_iter().flat_map(move |direction| {
+                segment.get_lanes(direction).enumerate().flat_map(move |(rank, lane)| {
+                    lane.actors.enumerate().map(move |(_, actor)| {
+                        (segment_id, Some((segment_id, direction, rank)), actor)
+                    })
+                })
+            });
+            off_road.chain(on_road)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use super::*;
+    use crate::{
+        road::{Segment, UTurnPolicy},
+        vehicle::VehicleParams,
+    };
+
+    /// Two junctions and a two-way segment between them
+    fn shuttle() -> (Network, JunctionId, JunctionId, SegmentId) {
+        let mut network = Network::new();
+        let west = network.add_junction(Point2::new(0.2, 0.5));
+        let east = network.add_junction(Point2::new(0.8, 0.5));
+        let (segment_id, segment) = network.add_segment(west, east).unwrap();
+        segment.add_lane(Forward);
+        segment.add_lane(Backward);
+        (network, west, east, segment_id)
+    }
+
+    fn validate(mut network: Network) -> Vec<ValidationIssue> {
+        network.connect_junctions(UTurnPolicy::Everywhere);
+        network.validate().issues
+    }
+
+    #[test]
+    fn well_formed_network_has_no_issues() {
+        let (network, _, _, _) = shuttle();
+        assert_eq!(validate(network), vec![]);
+    }
+
+    #[test]
+    fn dangling_segment() {
+        let (mut network, _, _, _) = shuttle();
+        let mut segment = Segment::new();
+        segment.add_lane(Forward);
+        let segment_id = network.segments.push(segment);
+        assert_eq!(validate(network), vec![ValidationIssue::UnlinkedSegment(segment_id)]);
+    }
+
+    #[test]
+    fn overlapping_junctions() {
+        let (mut network, west, east, _) = shuttle();
+        network.set_junction_radius(west, 0.4).unwrap();
+        network.set_junction_radius(east, 0.4).unwrap();
+        assert_eq!(validate(network), vec![ValidationIssue::OverlappingJunctions(west, east)]);
+    }
+
+    #[test]
+    fn lane_less_origin() {
+        let (mut network, west, _, segment_id) = shuttle();
+        let north = network.add_junction(Point2::new(0.2, 0.9));
+        let (spur_id, _) = network.add_segment(west, north).unwrap();
+        let agenda = vec![Agendum::TravelTo { segment_id, segment_side: Forward, pos_param: 0.5 }];
+        network.add_actor(spur_id, 0.5, Forward, VehicleParams::default(), agenda).unwrap();
+        assert_eq!(validate(network), vec![ValidationIssue::ActorWithoutLane(spur_id)]);
+    }
+
+    #[test]
+    fn unreachable_destination() {
+        let (mut network, _, _, segment_id) = shuttle();
+        let south_west = network.add_junction(Point2::new(0.2, 0.1));
+        let south_east = network.add_junction(Point2::new(0.8, 0.1));
+        let (destination, other) = network.add_segment(south_west, south_east).unwrap();
+        other.add_lane(Forward);
+        other.add_lane(Backward);
+        let agenda = vec![Agendum::TravelTo {
+            segment_id: destination,
+            segment_side: Forward,
+            pos_param: 0.5,
+        }];
+        network.add_actor(segment_id, 0.5, Forward, VehicleParams::default(), agenda).unwrap();
+        assert_eq!(
+            validate(network),
+            vec![ValidationIssue::UnreachableDestination { segment_id, destination }]
+        );
+    }
+
+    #[test]
+    fn self_loop_segment() {
+        let (mut network, west, _, _) = shuttle();
+        let (loop_id, _) = network.add_segment(west, west).unwrap();
+        assert_eq!(validate(network), vec![ValidationIssue::SelfLoopSegment(loop_id)]);
+    }
+
+    #[test]
+    fn coincident_junctions() {
+        let (mut network, _, east, _) = shuttle();
+        let coincident = network.add_junction(Point2::new(0.8, 0.5));
+        let north = network.add_junction(Point2::new(0.8, 0.9));
+        let (_, segment) = network.add_segment(coincident, north).unwrap();
+        segment.add_lane(Forward);
+        segment.add_lane(Backward);
+        assert_eq!(
+            validate(network),
+            vec![ValidationIssue::OverlappingJunctions(east, coincident)]
+        );
+    }
+
+    #[test]
+    fn segment_between_coincident_junctions() {
+        let (mut network, _, east, _) = shuttle();
+        let coincident = network.add_junction(Point2::new(0.8, 0.5));
+        let (segment_id, segment) = network.add_segment(east, coincident).unwrap();
+        segment.add_lane(Forward);
+        segment.add_lane(Backward);
+        assert_eq!(
+            validate(network),
+            vec![
+                ValidationIssue::ZeroLengthSegment(segment_id),
+                ValidationIssue::OverlappingJunctions(east, coincident)
+            ]
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+
+use rand::{distributions::WeightedIndex, Rng};
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::SimConfig, constants};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VehicleClass {
+    Car,
+    Truck,
+    Bus,
+    Bicycle,
+}
+
+/// A normal distribution, clamped to within half the mean either side so that
+/// outliers stay plausible
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ParamDistribution {
+    pub mean: f64,
+    pub std_dev: f64,
+}
+
+/// What vehicles of a class are like, on average
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VehicleClassSpec {
+    pub length: ParamDistribution,
+    /// distance per second
+    pub max_speed: ParamDistribution,
+    /// distance per second per second
+    pub acceleration: ParamDistribution,
+    /// distance per second per second, when braking
+    pub deceleration: ParamDistribution,
+    pub radius_visual: f64,
+}
+
+/// What one particular vehicle is like
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VehicleParams {
+    pub class: VehicleClass,
+    pub length: f64,
+    pub max_speed: f64,
+    pub acceleration: f64,
+    pub deceleration: f64,
+    pub radius_visual: f64,
+}
+
+impl ParamDistribution {
+    pub fn sample(&self, rng: &mut impl Rng) -> f64 {
+        match Normal::new(self.mean, self.std_dev) {
+            Ok(normal) => normal.sample(rng).clamp(0.5 * self.mean, 1.5 * self.mean),
+            // e.g. negative std dev
+            Err(_) => self.mean,
+        }
+    }
+}
+
+impl VehicleClass {
+    pub const ALL: [VehicleClass; 4] =
+        [VehicleClass::Car, VehicleClass::Truck, VehicleClass::Bus, VehicleClass::Bicycle];
+
+    pub fn default_spec(self) -> VehicleClassSpec {
+        let spread = |mean: f64, relative_std_dev: f64| ParamDistribution {
+            mean,
+            std_dev: mean * relative_std_dev,
+        };
+        let car_speed = constants::ACTOR_MAX_SPEED;
+        match self {
+            VehicleClass::Car => VehicleClassSpec {
+                length: spread(0.012, 0.1),
+                max_speed: spread(car_speed, 0.1),
+                acceleration: spread(car_speed / 5.0, 0.2),
+                deceleration: spread(car_speed / 3.0, 0.2),
+                radius_visual: constants::ACTOR_RADIUS_VISUAL,
+            },
+            VehicleClass::Truck => VehicleClassSpec {
+                length: spread(0.04, 0.2),
+                max_speed: spread(0.8 * car_speed, 0.1),
+                acceleration: spread(car_speed / 15.0, 0.2),
+                deceleration: spread(car_speed / 8.0, 0.2),
+                radius_visual: 1.6 * constants::ACTOR_RADIUS_VISUAL,
+            },
+            VehicleClass::Bus => VehicleClassSpec {
+                length: spread(0.036, 0.05),
+                max_speed: spread(0.8 * car_speed, 0.05),
+                acceleration: spread(car_speed / 12.0, 0.1),
+                deceleration: spread(car_speed / 6.0, 0.1),
+                radius_visual: 1.4 * constants::ACTOR_RADIUS_VISUAL,
+            },
+            VehicleClass::Bicycle => VehicleClassSpec {
+                length: spread(0.005, 0.05),
+                max_speed: spread(0.3 * car_speed, 0.2),
+                acceleration: spread(car_speed / 10.0, 0.2),
+                deceleration: spread(car_speed / 5.0, 0.2),
+                radius_visual: 0.5 * constants::ACTOR_RADIUS_VISUAL,
+            },
+        }
+    }
+
+    /// Picks a class at random, weighted by `SimConfig::vehicle_mix`
+    pub fn sample(config: &SimConfig, rng: &mut impl Rng) -> VehicleClass {
+        let classes: Vec<(&VehicleClass, &f64)> = config.vehicle_mix.iter().collect();
+        match WeightedIndex::new(classes.iter().map(|(_, weight)| **weight)) {
+            Ok(index) => *classes[index.sample(rng)].0,
+            // no weights, or none positive
+            Err(_) => VehicleClass::Car,
+        }
+    }
+}
+
+impl VehicleParams {
+    /// Picks a class as in `VehicleClass::sample`, then samples from it
+    pub fn sample_any(config: &SimConfig, rng: &mut impl Rng) -> Self {
+        let class = VehicleClass::sample(config, rng);
+        Self::sample(class, config, rng)
+    }
+
+    pub fn sample(class: VehicleClass, config: &SimConfig, rng: &mut impl Rng) -> Self {
+        let spec = config.get_vehicle_spec(class);
+        Self {
+            class,
+            length: spec.length.sample(rng),
+            max_speed: spec.max_speed.sample(rng),
+            acceleration: spec.acceleration.sample(rng),
+            deceleration: spec.deceleration.sample(rng),
+            radius_visual: spec.radius_visual,
+        }
+    }
+
+    /// How fast it goes when nothing is in the way
+    pub fn get_cruising_speed(&self, speed_limit: f64) -> f64 {
+        self.max_speed.min(speed_limit)
+    }
+
+    /// The class average, with no randomness
+    pub fn typical(class: VehicleClass, config: &SimConfig) -> Self {
+        Self::from_spec_means(class, &config.get_vehicle_spec(class))
+    }
+
+    fn from_spec_means(class: VehicleClass, spec: &VehicleClassSpec) -> Self {
+        Self {
+            class,
+            length: spec.length.mean,
+            max_speed: spec.max_speed.mean,
+            acceleration: spec.acceleration.mean,
+            deceleration: spec.deceleration.mean,
+            radius_visual: spec.radius_visual,
+        }
+    }
+}
+
+impl Default for VehicleParams {
+    fn default() -> Self {
+        Self::from_spec_means(VehicleClass::Car, &VehicleClass::Car.default_spec())
+    }
+}
+
+pub fn default_vehicle_classes() -> BTreeMap<VehicleClass, VehicleClassSpec> {
+    VehicleClass::ALL.iter().map(|class| (*class, class.default_spec())).collect()
+}
+
+pub fn default_vehicle_mix() -> BTreeMap<VehicleClass, f64> {
+    [
+        (VehicleClass::Car, 0.8),
+        (VehicleClass::Truck, 0.08),
+        (VehicleClass::Bus, 0.02),
+        (VehicleClass::Bicycle, 0.1),
+    ]
+    .into_iter()
+    .collect()
+}